@@ -0,0 +1,15 @@
+// Every opcode has to either decode to an instruction that encodes back to
+// the same bytes, or be rejected. Decoding must never panic.
+#![no_main]
+use chip_8::chip8::Instruction;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: [u8; 2]| {
+    if let Ok(instruction) = Instruction::from_bytes(data) {
+        assert_eq!(instruction.to_bytes(), data, "{}", instruction);
+
+        // Used by the debugger and the reports, these can't panic either
+        let _ = instruction.to_string();
+        let _ = instruction.pattern();
+    }
+});
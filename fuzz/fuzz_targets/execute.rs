@@ -0,0 +1,42 @@
+// Runs arbitrary bytes as a ROM for a fixed number of frames. Bad programs
+// are allowed to stop with a `Chip8Error`, but must never panic.
+#![no_main]
+use chip_8::chip8::{Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u64 = 60;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the quirks and which key is held down: bit 0
+    // display_wait, bit 5 shift_vy, bit 6 load_store_increment, bit 7 set
+    // means bits 1-4 are the key
+    let (config, rom) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let quirks = Quirks {
+        display_wait: config & 0x01 != 0,
+        shift_vy: config & 0x20 != 0,
+        load_store_increment: config & 0x40 != 0,
+    };
+    let key = if config & 0x80 != 0 {
+        Some((config >> 1) & 0x0F)
+    } else {
+        None
+    };
+
+    // ROMs too big to fit in memory are rejected up front
+    let mut headless = match Headless::new(rom, quirks) {
+        Ok(headless) => headless,
+        Err(_) => return,
+    };
+    headless.chip8_mut().set_seed(0);
+    headless.set_key(key);
+
+    while headless.frame() < FRAMES {
+        if headless.run_frame(STEPS_PER_FRAME).is_err() {
+            break;
+        }
+    }
+});
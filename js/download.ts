@@ -0,0 +1,14 @@
+// Save bytes made by the emulator as a file
+export function download(blob: Blob, filename: string) {
+    const link = window.document.createElement("a");
+    const url = URL.createObjectURL(blob);
+    link.href = url;
+    link.download = filename;
+    link.click();
+    // Revoking right away can cancel the download in some browsers
+    setTimeout(() => URL.revokeObjectURL(url), 0);
+}
+
+export function downloadBytes(bytes: Uint8Array, filename: string, type: string) {
+    download(new Blob([bytes], { type }), filename);
+}
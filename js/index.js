@@ -1,5 +1,223 @@
+import { download } from "./download";
+
 try {
-    import("../pkg/index.js").catch(console.error);
+    import("../pkg/index.js")
+        .then(chip8 => {
+            // Palette picker, choices are remembered by the emulator
+            const select = window.document.getElementById("palette");
+            for (const name of chip8.palette_presets()) {
+                select.add(new Option(name, name));
+            }
+            select.value = chip8.get_palette();
+            select.addEventListener("change", () => chip8.set_palette(select.value));
+
+            // Pixel exact screenshots, 10x so they're a usable size
+            window.document.getElementById("screenshot").addEventListener("click", () => {
+                const png = new Blob([chip8.screenshot_png(10)], { type: "image/png" });
+                download(png, "chip8.png");
+            });
+
+            const record = window.document.getElementById("record");
+            let recording = false;
+            record.addEventListener("click", () => {
+                if (recording) {
+                    const gif = new Blob([chip8.stop_recording(4)], { type: "image/gif" });
+                    download(gif, "chip8.gif");
+                    record.textContent = "Record GIF";
+                } else {
+                    chip8.start_recording();
+                    record.textContent = "Stop recording";
+                }
+                recording = !recording;
+            });
+
+            // Input movies are plain text files
+            const recordMovie = window.document.getElementById("record-movie");
+            let recordingMovie = false;
+            recordMovie.addEventListener("click", () => {
+                if (recordingMovie) {
+                    const movie = new Blob([chip8.stop_movie_recording()], { type: "text/plain" });
+                    download(movie, "chip8.c8m");
+                    recordMovie.textContent = "Record movie";
+                } else {
+                    chip8.start_movie_recording();
+                    recordMovie.textContent = "Stop movie";
+                }
+                recordingMovie = !recordingMovie;
+            });
+
+            const playMovie = window.document.getElementById("play-movie");
+            playMovie.addEventListener("change", () => {
+                const file = playMovie.files[0];
+                if (!file) {
+                    return;
+                }
+                file.text()
+                    .then(movie => chip8.play_movie(movie))
+                    .catch(e => window.alert(e))
+                    .finally(() => { playMovie.value = ""; });
+            });
+
+            // Save state slots, with a thumbnail of the screen when saved
+            const slotTable = window.document.getElementById("save-slots");
+            const importState = window.document.getElementById("import-state");
+            let importSlot = 0;
+            const showSaveSlots = () => {
+                slotTable.replaceChildren();
+                for (let slot = 0; slot < chip8.save_slots(); slot++) {
+                    const row = slotTable.insertRow();
+                    row.insertCell().textContent = slot;
+
+                    const time = chip8.save_slot_time(slot);
+                    const thumbnail = chip8.save_slot_thumbnail(slot, 2);
+                    if (thumbnail !== undefined) {
+                        const image = window.document.createElement("img");
+                        image.src = URL.createObjectURL(new Blob([thumbnail], { type: "image/png" }));
+                        image.onload = () => URL.revokeObjectURL(image.src);
+                        row.insertCell().appendChild(image);
+                    } else {
+                        row.insertCell();
+                    }
+                    row.insertCell().textContent = time !== undefined ? new Date(time).toLocaleString() : "Empty";
+
+                    const actions = row.insertCell();
+                    const button = (label, onClick, enabled = true) => {
+                        const button = window.document.createElement("button");
+                        button.textContent = label;
+                        button.disabled = !enabled;
+                        button.addEventListener("click", () => {
+                            try {
+                                onClick();
+                            } catch (e) {
+                                window.alert(e);
+                            }
+                            showSaveSlots();
+                        });
+                        actions.appendChild(button);
+                    };
+                    button("Save", () => chip8.save_state_to_slot(slot));
+                    button("Load", () => chip8.load_state_from_slot(slot), time !== undefined);
+                    button("Export", () => {
+                        const state = new Blob([chip8.export_save_slot(slot)], { type: "text/plain" });
+                        download(state, `chip8-slot${slot}.c8s`);
+                    }, time !== undefined);
+                    button("Import", () => {
+                        importSlot = slot;
+                        importState.click();
+                    });
+                }
+            };
+            showSaveSlots();
+            importState.addEventListener("change", () => {
+                const file = importState.files[0];
+                if (!file) {
+                    return;
+                }
+                file.text()
+                    .then(state => chip8.import_save_slot(importSlot, state))
+                    .then(showSaveSlots)
+                    .catch(e => window.alert(e))
+                    .finally(() => { importState.value = ""; });
+            });
+
+            // Offer to carry on from the last visit, then save again on the way out
+            const lastVisit = chip8.auto_save_time();
+            if (lastVisit !== undefined) {
+                const resumeCard = window.document.getElementById("resume-card");
+                window.document.getElementById("resume-time").textContent = new Date(lastVisit).toLocaleString();
+                resumeCard.classList.remove("hidden");
+                window.document.getElementById("resume").addEventListener("click", () => {
+                    chip8.resume_auto_save();
+                    resumeCard.classList.add("hidden");
+                });
+                window.document.getElementById("resume-dismiss").addEventListener("click", () => {
+                    resumeCard.classList.add("hidden");
+                });
+            }
+            window.addEventListener("pagehide", () => chip8.auto_save());
+
+            // Key bindings, saved for every ROM or just this one
+            const keyTable = window.document.getElementById("key-bindings");
+            const keysForRom = window.document.getElementById("keys-for-rom");
+            const showKeyBindings = () => {
+                keyTable.replaceChildren();
+                for (let key = 0; key < 16; key++) {
+                    const row = keyTable.insertRow();
+                    row.insertCell().textContent = key.toString(16).toUpperCase();
+                    const codes = row.insertCell();
+                    codes.textContent = chip8.key_binding_codes(key).join(", ");
+
+                    const bind = window.document.createElement("button");
+                    bind.textContent = "Bind";
+                    bind.addEventListener("click", () => {
+                        codes.textContent = "Press a key...";
+                        chip8.capture_key_binding(key, keysForRom.checked, showKeyBindings);
+                    });
+                    row.insertCell().appendChild(bind);
+                }
+            };
+            showKeyBindings();
+            window.document.getElementById("keys-reset").addEventListener("click", () => {
+                chip8.reset_key_bindings(keysForRom.checked);
+                showKeyBindings();
+            });
+
+            // Hotkeys, bound one key each
+            const hotkeyTable = window.document.getElementById("hotkeys");
+            const showHotkeys = () => {
+                const codes = new Map(chip8.get_hotkeys().split(",").map(binding => binding.split("=")));
+                hotkeyTable.replaceChildren();
+                for (const name of chip8.hotkey_names()) {
+                    const row = hotkeyTable.insertRow();
+                    row.insertCell().textContent = name;
+                    const code = row.insertCell();
+                    code.textContent = codes.get(name) ?? "";
+
+                    const bind = window.document.createElement("button");
+                    bind.textContent = "Bind";
+                    bind.addEventListener("click", () => {
+                        code.textContent = "Press a key...";
+                        chip8.capture_hotkey(name, showHotkeys);
+                    });
+                    row.insertCell().appendChild(bind);
+                }
+            };
+            showHotkeys();
+
+            // Gamepad bindings are remembered per ROM by the emulator
+            const bindings = window.document.getElementById("gamepad-bindings");
+            bindings.value = chip8.get_gamepad_bindings();
+            window.document.getElementById("gamepad-save").addEventListener("click", () => {
+                try {
+                    chip8.set_gamepad_bindings(bindings.value);
+                    bindings.value = chip8.get_gamepad_bindings();
+                } catch (e) {
+                    window.alert(e);
+                }
+            });
+
+            // Quirk comparison, restarted every time it's asked for
+            const compare = window.document.getElementById("compare");
+            let comparison = null;
+            window.document.getElementById("compare-start").addEventListener("click", () => {
+                comparison?.free();
+                comparison = null;
+                try {
+                    comparison = new chip8.QuirkComparison(
+                        compare,
+                        window.document.getElementById("compare-left"),
+                        window.document.getElementById("compare-right"),
+                        window.document.getElementById("compare-divergence"),
+                        window.document.getElementById("compare-left-quirks").value,
+                        window.document.getElementById("compare-right-quirks").value,
+                    );
+                    compare.focus();
+                } catch (e) {
+                    window.alert(e);
+                }
+            });
+        })
+        .catch(console.error);
 } catch (e) {
     console.error(e);
     window.document.getElementById("errorcard").classList.remove("hidden");
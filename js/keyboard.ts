@@ -1,31 +1,118 @@
-// Keyboard allows querying which Chip-8 key is currently pressed
+// Keyboard records every key going up or down, with the time it happened.
+// Physical keys are mapped to Chip-8 keys by the bindings on the rust side.
 // https://codepen.io/edison-moreland/pen/PowOGqO
-type Chip8Key = -1|0|1|2|3|4|5|6|7|8|9|10|11|12|13|14|15
+export type Chip8Key = -1|0|1|2|3|4|5|6|7|8|9|10|11|12|13|14|15
+
+// Either a physical key (`KeyboardEvent.code`) or a Chip-8 key
+// pressed on another input (e.g. the touch keypad)
+interface KeyEvent {
+    time: number;
+    code?: string;
+    key?: Chip8Key;
+    pressed: boolean;
+}
+
+// Whether a key went to something being typed in, e.g. the gamepad bindings
+// box, and so isn't meant for the emulator
+function isTyping(target: EventTarget | null): boolean {
+    if (!(target instanceof HTMLElement)) {
+        return false;
+    }
+
+    const tag = target.tagName;
+    return tag === "INPUT" || tag === "TEXTAREA" || tag === "SELECT" || target.isContentEditable;
+}
 
 export class KeyboardListener {
-    keyMap: Map<string, Chip8Key>;
-    keyPressed: Chip8Key;
+    // Events since the emulator last took them, in time order
+    events: KeyEvent[];
+    // Physical keys held down, so held keys don't repeat
+    heldCodes: Set<string>;
+    // Keys that control the emulator, they never reach the game
+    hotkeyCodes: Set<string>;
+    // Hotkeys pressed since the emulator last took them
+    hotkeys: string[];
+    // Receives the next key pressed instead of the emulator
+    capturing: ((code: string) => void) | null;
+    // Keys are only heard while focus is inside this, the window hears
+    // every key on the page
+    scope: EventTarget;
+
+    constructor(scope: EventTarget) {
+        this.events = [];
+        this.heldCodes = new Set();
+        this.hotkeyCodes = new Set();
+        this.hotkeys = [];
+        this.capturing = null;
+        this.scope = scope;
+
+        scope.addEventListener("keydown", this);
+        scope.addEventListener("keyup", this);
+        // Keys let go after focus moves away never send a keyup
+        scope.addEventListener("blur", this);
+    }
+
+    // Stop listening, the emulator using this has gone
+    detach() {
+        this.scope.removeEventListener("keydown", this);
+        this.scope.removeEventListener("keyup", this);
+        this.scope.removeEventListener("blur", this);
+    }
+
+    // Whether keys pressed now would be heard, other inputs (e.g. gamepads)
+    // go to the same emulator as the keyboard
+    hasFocus(): boolean {
+        return this.scope === window
+            || (this.scope instanceof Node && this.scope.contains(window.document.activeElement));
+    }
+
+    takeEvents(): KeyEvent[] {
+        const events = this.events;
+        this.events = [];
+        return events;
+    }
 
-    constructor() {
-        // -1 = No key pressed
-        this.keyPressed = -1;
+    setHotkeyCodes(codes: string[]) {
+        this.hotkeyCodes = new Set(codes);
+    }
+
+    takeHotkeys(): string[] {
+        const hotkeys = this.hotkeys;
+        this.hotkeys = [];
+        return hotkeys;
+    }
 
-        // Maps physical keys to the chip-8's hex keyboard (0-15)
-        this.keyMap = new Map([
-            ["Digit1", 1], ["Digit2", 2], ["Digit3", 3], ["Digit4", 12],
-            ["KeyQ", 4], ["KeyW", 5], ["KeyE", 6], ["KeyR", 13],
-            ["KeyA", 7], ["KeyS", 8], ["KeyD", 9], ["KeyF", 14],
-            ["KeyZ", 10], ["KeyX", 0], ["KeyC", 11], ["KeyV", 15],
-        ]);
-        window.addEventListener("keydown", this);
-        window.addEventListener("keyup", this);
+    press(key: Chip8Key) {
+        this.events.push({ time: window.performance.now(), key, pressed: true });
     }
 
-    getKey(): Chip8Key {
-        return this.keyPressed;
+    release(key: Chip8Key) {
+        this.events.push({ time: window.performance.now(), key, pressed: false });
     }
 
-    handleEvent(event: KeyboardEvent) {
+    capture(callback: (code: string) => void) {
+        this.capturing = callback;
+    }
+
+    handleEvent(event: Event) {
+        if (event.type === "blur") {
+            for (const code of this.heldCodes) {
+                this.events.push({ time: window.performance.now(), code, pressed: false });
+            }
+            this.heldCodes.clear();
+            return;
+        }
+
+        // Keys for an emulator inside the page don't also go to one
+        // listening on the whole page
+        if (this.scope !== window) {
+            event.stopPropagation();
+        }
+
+        this.handleKey(event as KeyboardEvent);
+    }
+
+    handleKey(event: KeyboardEvent) {
         // The two event handlers were combined so that 
         // "Keyboard" implements the EventListener interface,
         // which allows access to "this" while handling
@@ -33,26 +120,31 @@ export class KeyboardListener {
         // to the global scope.
         // https://developer.mozilla.org/en-US/docs/Web/API/EventListener
 
-        const key = this.keyMap.get(event.code);
-
-        // Hop out if key was not a chip-8 key
-        if (key === undefined) { return }
-
         switch (event.type) {
             case "keydown":
-                // Only change key if a key isn't already pressed
-                if (this.keyPressed === -1) {
-                    this.keyPressed = key
+                if (this.capturing !== null) {
+                    event.preventDefault();
+                    const callback = this.capturing;
+                    this.capturing = null;
+                    callback(event.code);
+                } else if (isTyping(event.target)) {
+                    // Leave it to the text field
+                } else if (this.hotkeyCodes.has(event.code)) {
+                    event.preventDefault();
+                    if (!event.repeat) {
+                        this.hotkeys.push(event.code);
+                    }
+                } else if (!this.heldCodes.has(event.code)) {
+                    this.heldCodes.add(event.code);
+                    this.events.push({ time: event.timeStamp, code: event.code, pressed: true });
                 }
                 break;
 
             case "keyup":
-                // Only change if the lifted key is the 
-                // same key that was already pressed
-                if (this.keyPressed === key) {
-                    this.keyPressed = -1;
+                if (this.heldCodes.delete(event.code)) {
+                    this.events.push({ time: event.timeStamp, code: event.code, pressed: false });
                 }
                 break;
         }
     }
-}
\ No newline at end of file
+}
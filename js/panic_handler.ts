@@ -1,5 +1,11 @@
-export function panicHandler() {
-    // We expect rust to write panic information to the console
-    // our job is just to inform the user that something bad has happend
+export function panicHandler(message: string) {
+    // Rust also writes panic information to the console. The emulator can't
+    // carry on after a panic, so only the message is shown, without the
+    // buttons a Chip-8 error gets.
+    const details = window.document.getElementById("error-details");
+    if (details) {
+        details.textContent = message;
+    }
+    window.document.getElementById("error-actions")?.classList.add("hidden");
     window.document.getElementById("errorcard")?.classList.remove("hidden");
-}
\ No newline at end of file
+}
@@ -0,0 +1,91 @@
+// On-screen hex keypad for touch screens, every finger is tracked on its own
+// so keys can be held down together
+import { Chip8Key, KeyboardListener } from "./keyboard";
+
+// Same layout as the COSMAC VIP keypad
+const LAYOUT: Chip8Key[] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// Short buzz on press, where the device supports it
+const HAPTIC_MS = 10;
+
+export class TouchKeypad {
+    buttons: Map<Chip8Key, HTMLButtonElement>;
+    polledKeys: number;
+    // Hides keys the ROM never checks while ticked
+    polledCheckbox: HTMLInputElement | null;
+
+    // Without a container there's no keypad, e.g. for small embedded emulators
+    constructor(listener: KeyboardListener, container: HTMLElement | null, polledCheckbox: HTMLInputElement | null) {
+        this.buttons = new Map();
+        this.polledKeys = 0;
+        this.polledCheckbox = polledCheckbox;
+
+        if (container === null) { return }
+
+        for (const key of LAYOUT) {
+            const button = window.document.createElement("button");
+            button.textContent = key.toString(16).toUpperCase();
+
+            // Pointers that are holding this key down
+            const pointers = new Set<number>();
+            const down = (event: PointerEvent) => {
+                event.preventDefault();
+                if (pointers.size === 0) {
+                    listener.press(key);
+                    window.navigator.vibrate?.(HAPTIC_MS);
+                }
+                pointers.add(event.pointerId);
+                button.classList.add("inverse");
+            };
+            const up = (event: PointerEvent) => {
+                pointers.delete(event.pointerId);
+                if (pointers.size === 0) {
+                    listener.release(key);
+                    button.classList.remove("inverse");
+                }
+            };
+
+            button.addEventListener("pointerdown", down);
+            button.addEventListener("pointerup", up);
+            button.addEventListener("pointercancel", up);
+            button.addEventListener("pointerleave", up);
+            button.addEventListener("contextmenu", event => event.preventDefault());
+
+            this.buttons.set(key, button);
+            container.appendChild(button);
+        }
+
+        polledCheckbox?.addEventListener("change", () => this.update());
+    }
+
+    // Bit per key the ROM has checked, see `Chip8::polled_keys`
+    setPolledKeys(polledKeys: number) {
+        if (polledKeys !== this.polledKeys) {
+            this.polledKeys = polledKeys;
+            this.update();
+        }
+    }
+
+    // Take the keypad off the page
+    detach() {
+        for (const button of this.buttons.values()) {
+            button.remove();
+        }
+        this.buttons.clear();
+    }
+
+    update() {
+        // Until the ROM checks a key there's nothing to go on, so show them all
+        const filter = (this.polledCheckbox?.checked ?? false) && this.polledKeys !== 0;
+
+        for (const [key, button] of this.buttons) {
+            const polled = (this.polledKeys & (1 << key)) !== 0;
+            button.style.visibility = filter && !polled ? "hidden" : "visible";
+        }
+    }
+}
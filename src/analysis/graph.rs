@@ -0,0 +1,387 @@
+// Basic blocks and the call graph of a ROM, for reading and documenting how
+// it's put together. Code is found the same way as for linting, by following
+// every path from the start of the program.
+//
+// A block ends at a skip, a jump, a return, or a BNNN. Skips are branches
+// both ways, to the next instruction and the one after it. BNNN goes
+// somewhere from NNN on that depends on V0, so it's left as an exit to NNN
+// that isn't followed. Calls don't end blocks, the subroutine is drawn as
+// its own group of blocks instead.
+//
+// `Graph::to_dot` writes the whole thing for Graphviz, e.g.
+//
+//   chip8-graph game.ch8 | dot -Tsvg > game.svg
+use super::{Flow, Image};
+use crate::chip8::{platform_only, Instruction, PROGRAM_START};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitKind {
+    // Runs on into the next block
+    Next,
+    // Where a skip goes when it skips, and when it doesn't
+    Skipped,
+    NotSkipped,
+    Jump,
+    // BNNN, to the target plus V0
+    Computed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit {
+    pub kind: ExitKind,
+    // Outside of the ROM if there's no block there
+    pub target: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    // Address and opcode of each instruction, in order. The last one may
+    // not decode, in which case nothing leaves the block.
+    pub instructions: Vec<(u16, u16)>,
+    pub exits: Vec<Exit>,
+    // Subroutines called from the block, in order
+    pub calls: Vec<u16>,
+}
+
+// The program, or a subroutine, and the blocks reached from its entry
+// without going through a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routine {
+    pub entry: u16,
+    pub blocks: BTreeSet<u16>,
+    pub calls: BTreeSet<u16>,
+}
+
+pub struct Graph {
+    blocks: BTreeMap<u16, Block>,
+    routines: BTreeMap<u16, Routine>,
+}
+
+impl Graph {
+    pub fn new(rom: &[u8]) -> Graph {
+        let image = Image::new(rom);
+
+        // Every instruction reached and the ones blocks start at
+        let mut reached: BTreeMap<u16, Option<Instruction>> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut entries = BTreeSet::new();
+        let mut todo = Vec::new();
+
+        let start = image.start();
+        if image.contains(start) {
+            leaders.insert(start);
+            entries.insert(start);
+            todo.push(start);
+        }
+
+        while let Some(addr) = todo.pop() {
+            if reached.contains_key(&addr) {
+                continue;
+            }
+            let instruction = image.opcode(addr).and_then(decode);
+            reached.insert(addr, instruction);
+
+            let flow = match instruction {
+                Some(instruction) => Flow::of(&instruction),
+                None => continue,
+            };
+            let next = addr + 2;
+            let mut follow = |target: u16, leader: bool| {
+                if image.contains(target) {
+                    if leader {
+                        leaders.insert(target);
+                    }
+                    todo.push(target);
+                }
+            };
+
+            match flow {
+                Flow::Next => follow(next, false),
+                Flow::Skip => {
+                    follow(next, true);
+                    follow(next + 2, true);
+                }
+                Flow::Jump(target) => follow(target, true),
+                Flow::Call(target) => {
+                    follow(target, true);
+                    follow(next, false);
+                    if image.contains(target) {
+                        entries.insert(target);
+                    }
+                }
+                Flow::Return | Flow::Computed(_) => {}
+            }
+        }
+
+        let blocks: BTreeMap<u16, Block> = leaders
+            .iter()
+            .map(|&start| (start, build_block(&image, &reached, &leaders, start)))
+            .collect();
+        let routines = entries
+            .iter()
+            .map(|&entry| (entry, find_routine(&blocks, entry)))
+            .collect();
+
+        Graph { blocks, routines }
+    }
+
+    pub fn block(&self, start: u16) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    // In address order
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    // The program first, then subroutines in address order
+    pub fn routines(&self) -> impl Iterator<Item = &Routine> {
+        self.routines.values()
+    }
+
+    // A Graphviz digraph with a box of disassembly for each block, grouped
+    // by routine. Calls are dashed, computed jumps dotted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph rom {\n");
+        dot += "  node [shape=box, fontname=\"monospace\"];\n";
+
+        // Blocks shared between routines are drawn in the first one
+        let mut drawn = BTreeSet::new();
+        for routine in self.routines() {
+            let name = match routine.entry as usize {
+                PROGRAM_START => String::from("program"),
+                entry => format!("subroutine {:#06X}", entry),
+            };
+            writeln!(dot, "  subgraph cluster_{:04X} {{", routine.entry).unwrap();
+            writeln!(dot, "    label=\"{}\";", name).unwrap();
+
+            for start in &routine.blocks {
+                if drawn.insert(*start) {
+                    writeln!(dot, "    {}", self.dot_node(&self.blocks[start])).unwrap();
+                }
+            }
+            dot += "  }\n";
+        }
+
+        // Anything an edge goes to that isn't a block
+        let mut outside = BTreeSet::new();
+        for block in self.blocks() {
+            for exit in &block.exits {
+                let style = match exit.kind {
+                    ExitKind::Next | ExitKind::Jump => "",
+                    ExitKind::Skipped => " [label=\"skip\"]",
+                    ExitKind::NotSkipped => " [label=\"no skip\"]",
+                    ExitKind::Computed => " [style=dotted, label=\"+ V0\"]",
+                };
+                if !self.blocks.contains_key(&exit.target) {
+                    outside.insert(exit.target);
+                }
+                writeln!(
+                    dot,
+                    "  b{:04X} -> b{:04X}{};",
+                    block.start, exit.target, style
+                )
+                .unwrap();
+            }
+
+            for call in &block.calls {
+                if !self.blocks.contains_key(call) {
+                    outside.insert(*call);
+                }
+                writeln!(
+                    dot,
+                    "  b{:04X} -> b{:04X} [style=dashed, label=\"call\"];",
+                    block.start, call
+                )
+                .unwrap();
+            }
+        }
+
+        for addr in outside {
+            writeln!(
+                dot,
+                "  b{:04X} [shape=plaintext, label=\"{:#06X}\"];",
+                addr, addr
+            )
+            .unwrap();
+        }
+
+        dot += "}\n";
+        dot
+    }
+
+    fn dot_node(&self, block: &Block) -> String {
+        let mut label = String::new();
+        for (addr, opcode) in &block.instructions {
+            // "\l" ends a left aligned line
+            write!(
+                label,
+                "{:#06X}  {:04X}  {}\\l",
+                addr,
+                opcode,
+                Instruction::disassemble(opcode.to_be_bytes())
+            )
+            .unwrap();
+        }
+
+        format!("b{:04X} [label=\"{}\"];", block.start, label)
+    }
+}
+
+// Only instructions this interpreter runs, so blocks end where it would stop
+fn decode(opcode: u16) -> Option<Instruction> {
+    if platform_only(opcode).is_some() {
+        return None;
+    }
+
+    Instruction::from_bytes(opcode.to_be_bytes()).ok()
+}
+
+fn build_block(
+    image: &Image,
+    reached: &BTreeMap<u16, Option<Instruction>>,
+    leaders: &BTreeSet<u16>,
+    start: u16,
+) -> Block {
+    let mut block = Block {
+        start,
+        instructions: Vec::new(),
+        exits: Vec::new(),
+        calls: Vec::new(),
+    };
+    let exit = |kind, target| Exit { kind, target };
+
+    let mut addr = start;
+    loop {
+        block
+            .instructions
+            .push((addr, image.opcode(addr).unwrap_or_default()));
+
+        let instruction = match reached.get(&addr) {
+            Some(Some(instruction)) => instruction,
+            _ => return block,
+        };
+        let next = addr + 2;
+
+        match Flow::of(instruction) {
+            Flow::Next => {}
+            Flow::Call(target) => block.calls.push(target),
+            Flow::Skip => {
+                block.exits.push(exit(ExitKind::NotSkipped, next));
+                block.exits.push(exit(ExitKind::Skipped, next + 2));
+                return block;
+            }
+            Flow::Jump(target) => {
+                block.exits.push(exit(ExitKind::Jump, target));
+                return block;
+            }
+            Flow::Computed(target) => {
+                block.exits.push(exit(ExitKind::Computed, target));
+                return block;
+            }
+            Flow::Return => return block,
+        }
+
+        if leaders.contains(&next) || !reached.contains_key(&next) {
+            block.exits.push(exit(ExitKind::Next, next));
+            return block;
+        }
+        addr = next;
+    }
+}
+
+fn find_routine(blocks: &BTreeMap<u16, Block>, entry: u16) -> Routine {
+    let mut routine = Routine {
+        entry,
+        blocks: BTreeSet::new(),
+        calls: BTreeSet::new(),
+    };
+
+    let mut todo = vec![entry];
+    while let Some(start) = todo.pop() {
+        let block = match blocks.get(&start) {
+            Some(block) if routine.blocks.insert(start) => block,
+            _ => continue,
+        };
+
+        routine.calls.extend(&block.calls);
+        todo.extend(block.exits.iter().map(|exit| exit.target));
+    }
+
+    routine
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROM: [u8; 16] = [
+        0x22, 0x0A, // 0x200 CALL 0x20A
+        0x3F, 0x00, // 0x202 SE VF, 0x00
+        0x12, 0x00, // 0x204 JP 0x200
+        0xB3, 0x00, // 0x206 JP V0, 0x300
+        0x00, 0x00, // 0x208 never run
+        0x60, 0x01, // 0x20A LD V0, 0x01
+        0x00, 0xEE, // 0x20C RET
+        0x13, 0x00, // 0x20E never run
+    ];
+
+    #[test]
+    fn test_blocks() {
+        let graph = Graph::new(&ROM);
+        let starts: Vec<u16> = graph.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, [0x200, 0x204, 0x206, 0x20A]);
+
+        let first = graph.block(0x200).unwrap();
+        assert_eq!(first.instructions, [(0x200, 0x220A), (0x202, 0x3F00)]);
+        assert_eq!(first.calls, [0x20A]);
+        assert_eq!(
+            first.exits,
+            [
+                Exit {
+                    kind: ExitKind::NotSkipped,
+                    target: 0x204
+                },
+                Exit {
+                    kind: ExitKind::Skipped,
+                    target: 0x206
+                },
+            ]
+        );
+
+        let computed = graph.block(0x206).unwrap();
+        assert_eq!(computed.exits[0].kind, ExitKind::Computed);
+        assert!(graph.block(0x20A).unwrap().exits.is_empty());
+
+        let routines: Vec<&Routine> = graph.routines().collect();
+        assert_eq!(routines.len(), 2);
+        assert_eq!(
+            routines[0].blocks.iter().copied().collect::<Vec<_>>(),
+            [0x200, 0x204, 0x206]
+        );
+        assert_eq!(
+            routines[0].calls.iter().copied().collect::<Vec<_>>(),
+            [0x20A]
+        );
+        assert_eq!(routines[1].entry, 0x20A);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = Graph::new(&ROM).to_dot();
+
+        assert!(dot.starts_with("digraph rom {\n"));
+        assert!(dot.contains("subgraph cluster_020A {\n    label=\"subroutine 0x020A\";"));
+        assert!(dot.contains("b0200 [label=\"0x0200  220A  CALL 0x020A\\l"));
+        assert!(dot.contains("b0200 -> b020A [style=dashed, label=\"call\"];"));
+        assert!(dot.contains("b0200 -> b0206 [label=\"skip\"];"));
+        // Nothing is known about where the computed jump lands
+        assert!(dot.contains("b0206 -> b0300 [style=dotted, label=\"+ V0\"];"));
+        assert!(dot.contains("b0300 [shape=plaintext, label=\"0x0300\"];"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
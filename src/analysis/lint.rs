@@ -0,0 +1,355 @@
+// Warnings about a ROM from its bytes alone, for mistakes that would
+// otherwise only show up as a `Chip8Error`, or odd behaviour, once the ROM
+// runs into them. Code is found by following every path from the start of
+// the program, so data is only looked at if something can run into it.
+//
+// What I holds is followed along the way while it's set by ANNN, which is
+// enough to check most sprite draws and stores. Anything else that moves I
+// makes it unknown, and nothing is said about it until the next ANNN.
+use super::{Flow, Image};
+use crate::chip8::{platform_only, Instruction, Platform, CHIP8_FONT, FONT_START, MEM_SIZE};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+// Just past the built in font
+const FONT_END: u16 = (FONT_START + CHIP8_FONT.len()) as u16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // Stops the ROM with a `Chip8Error` when it's reached
+    Error,
+    // Probably a mistake, but the ROM carries on
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintKind {
+    // An opcode that isn't an instruction
+    Undecodable(u16),
+    // An opcode that only a later interpreter has
+    VariantOpcode(u16, Platform),
+    // A jump or call to an address outside of the ROM
+    OutsideRom(u16),
+    // Carrying on past the last instruction in the ROM
+    RunsOffEnd,
+    // DXYN reading past the end of the ROM, with what I was
+    SpriteOutsideRom { i: u16, height: u8 },
+    // FX33 or FX55 storing over the font, with what I was
+    FontWrite(u16),
+    // 00EE outside of a subroutine, which starts the program over
+    ReturnWithoutCall,
+    // A subroutine with no way back, so every call to it leaves its return
+    // address on the stack
+    NeverReturns,
+}
+
+impl LintKind {
+    pub fn severity(&self) -> Severity {
+        match *self {
+            LintKind::Undecodable(_)
+            | LintKind::VariantOpcode(..)
+            | LintKind::OutsideRom(_)
+            | LintKind::RunsOffEnd => Severity::Error,
+            // Only past the end of memory is the read an error
+            LintKind::SpriteOutsideRom { i, height } if i as usize + height as usize > MEM_SIZE => {
+                Severity::Error
+            }
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LintKind::Undecodable(opcode) => write!(f, "{:04X} isn't an instruction", opcode),
+            LintKind::VariantOpcode(opcode, platform) => {
+                write!(f, "{:04X} is {} only", opcode, platform)
+            }
+            LintKind::OutsideRom(addr) => write!(f, "goes to {:#06X}, outside of the ROM", addr),
+            LintKind::RunsOffEnd => write!(f, "runs off the end of the ROM"),
+            LintKind::SpriteOutsideRom { i, height } => write!(
+                f,
+                "draws {} bytes from I = {:#06X}, past the end of the ROM",
+                height, i
+            ),
+            LintKind::FontWrite(i) => write!(f, "stores to I = {:#06X}, over the font", i),
+            LintKind::ReturnWithoutCall => {
+                write!(f, "returns without a call, which restarts the program")
+            }
+            LintKind::NeverReturns => write!(
+                f,
+                "subroutine never returns, each call leaves an address on the stack"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lint {
+    // Where the instruction is, or the subroutine for `NeverReturns`
+    pub address: u16,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+// "0x0204: warning: stores to I = 0x0010, over the font"
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#06X}: {}: {}",
+            self.address,
+            self.severity(),
+            self.kind
+        )
+    }
+}
+
+// Everything wrong with `rom` that could be found, in address order
+pub fn lint(rom: &[u8]) -> Vec<Lint> {
+    let image = Image::new(rom);
+    let mut walk = Walk {
+        image,
+        seen: HashMap::new(),
+        todo: Vec::new(),
+        called: BTreeSet::new(),
+        exits: HashSet::new(),
+        lints: BTreeSet::new(),
+    };
+
+    if image.contains(image.start()) {
+        walk.visit(None, image.start(), None);
+    }
+    while let Some((routine, addr, i)) = walk.todo.pop() {
+        walk.step(routine, addr, i);
+    }
+
+    for routine in &walk.called {
+        if !walk.exits.contains(routine) {
+            walk.lints.insert(Lint {
+                address: *routine,
+                kind: LintKind::NeverReturns,
+            });
+        }
+    }
+
+    walk.lints.into_iter().collect()
+}
+
+// The subroutine code was reached in, `None` for the main program
+type Routine = Option<u16>;
+
+struct Walk<'a> {
+    image: Image<'a>,
+    // What I is at each instruction reached, `None` once it could be more
+    // than one thing
+    seen: HashMap<(Routine, u16), Option<u16>>,
+    todo: Vec<(Routine, u16, Option<u16>)>,
+    called: BTreeSet<u16>,
+    // Subroutines that return, or that go somewhere that can't be followed
+    exits: HashSet<u16>,
+    lints: BTreeSet<Lint>,
+}
+
+impl<'a> Walk<'a> {
+    // Queue up `addr`, unless it's already been walked with what I is now
+    fn visit(&mut self, routine: Routine, addr: u16, i: Option<u16>) {
+        let i = match self.seen.get(&(routine, addr)) {
+            None => i,
+            Some(&seen) if seen == i || seen.is_none() => return,
+            Some(_) => None,
+        };
+
+        self.seen.insert((routine, addr), i);
+        self.todo.push((routine, addr, i));
+    }
+
+    // Carry on at `to`, or say why not
+    fn follow(&mut self, routine: Routine, from: u16, to: u16, i: Option<u16>, outside: LintKind) {
+        if self.image.contains(to) {
+            self.visit(routine, to, i);
+        } else {
+            self.lint(from, outside);
+        }
+    }
+
+    fn lint(&mut self, address: u16, kind: LintKind) {
+        self.lints.insert(Lint { address, kind });
+    }
+
+    fn exit(&mut self, routine: Routine) {
+        if let Some(routine) = routine {
+            self.exits.insert(routine);
+        }
+    }
+
+    fn step(&mut self, routine: Routine, addr: u16, i: Option<u16>) {
+        let opcode = match self.image.opcode(addr) {
+            Some(opcode) => opcode,
+            None => return,
+        };
+
+        if let Some(platform) = platform_only(opcode) {
+            self.lint(addr, LintKind::VariantOpcode(opcode, platform));
+            return self.exit(routine);
+        }
+        let instruction = match Instruction::from_bytes(opcode.to_be_bytes()) {
+            Ok(instruction) => instruction,
+            Err(()) => {
+                self.lint(addr, LintKind::Undecodable(opcode));
+                return self.exit(routine);
+            }
+        };
+
+        let i = self.check_i(addr, &instruction, i);
+        let next = addr + 2;
+
+        match Flow::of(&instruction) {
+            Flow::Next => self.follow(routine, addr, next, i, LintKind::RunsOffEnd),
+            Flow::Skip => {
+                self.follow(routine, addr, next, i, LintKind::RunsOffEnd);
+                self.follow(routine, addr, next + 2, i, LintKind::RunsOffEnd);
+            }
+            Flow::Jump(target) => {
+                self.follow(routine, addr, target, i, LintKind::OutsideRom(target))
+            }
+            Flow::Call(target) => {
+                if self.image.contains(target) {
+                    self.called.insert(target);
+                }
+                self.follow(Some(target), addr, target, i, LintKind::OutsideRom(target));
+                // The subroutine could have left anything in I
+                self.follow(routine, addr, next, None, LintKind::RunsOffEnd);
+            }
+            Flow::Return => match routine {
+                Some(_) => self.exit(routine),
+                None => self.lint(addr, LintKind::ReturnWithoutCall),
+            },
+            Flow::Computed(_) => self.exit(routine),
+        }
+    }
+
+    // Check what `instruction` does with I, and what I is after it
+    fn check_i(&mut self, addr: u16, instruction: &Instruction, i: Option<u16>) -> Option<u16> {
+        match *instruction {
+            Instruction::LoadAddress(nnn) => Some(nnn),
+            Instruction::Draw(_, _, height) => {
+                if let Some(i) = i {
+                    if i + height as u16 > self.image.end() {
+                        self.lint(addr, LintKind::SpriteOutsideRom { i, height });
+                    }
+                }
+                i
+            }
+            Instruction::LoadMemoryBcd(_) => {
+                self.check_store(addr, i);
+                i
+            }
+            // I may or may not move, depending on the quirks
+            Instruction::LoadMemoryRegisters(_) => {
+                self.check_store(addr, i);
+                None
+            }
+            Instruction::LoadRegistersMemory(_)
+            | Instruction::AddAddress(_)
+            | Instruction::LoadAddressDigit(_) => None,
+            _ => i,
+        }
+    }
+
+    fn check_store(&mut self, addr: u16, i: Option<u16>) {
+        if let Some(i) = i.filter(|i| *i < FONT_END) {
+            self.lint(addr, LintKind::FontWrite(i));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clean_rom() {
+        let rom = [
+            0x22, 0x08, // CALL 0x208
+            0xA2, 0x0C, // LD I, 0x20C
+            0xD0, 0x11, // DRW V0, V1, 1
+            0x12, 0x06, // JP 0x206
+            0x60, 0x01, // LD V0, 0x01
+            0x00, 0xEE, // RET
+            0xFF, 0x00, // Sprite, never run
+        ];
+        assert_eq!(lint(&rom), []);
+    }
+
+    #[test]
+    fn test_everything_wrong() {
+        let rom = [
+            0xA0, 0x10, // LD I, 0x010
+            0xF2, 0x55, // LD [I], V2
+            0x22, 0x10, // CALL 0x210
+            0x3F, 0x00, // SE VF, 0x00
+            0x00, 0xFF, // HIGH
+            0xA2, 0x16, // LD I, 0x216
+            0xD0, 0x1F, // DRW V0, V1, 15
+            0x00, 0xEE, // RET
+            0x3F, 0x01, // SE VF, 0x01
+            0x13, 0x00, // JP 0x300
+            0x12, 0x10, // JP 0x210
+            0x00, 0x00, // Sprite
+        ];
+        let found: Vec<(u16, LintKind)> = lint(&rom)
+            .into_iter()
+            .map(|lint| (lint.address, lint.kind))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (0x202, LintKind::FontWrite(0x010)),
+                (0x208, LintKind::VariantOpcode(0x00FF, Platform::SuperChip)),
+                (
+                    0x20C,
+                    LintKind::SpriteOutsideRom {
+                        i: 0x216,
+                        height: 15
+                    }
+                ),
+                (0x20E, LintKind::ReturnWithoutCall),
+                (0x210, LintKind::NeverReturns),
+                (0x212, LintKind::OutsideRom(0x300)),
+            ]
+        );
+
+        assert_eq!(
+            lint(&[0x60, 0x01, 0x00, 0x00]),
+            [Lint {
+                address: 0x202,
+                kind: LintKind::Undecodable(0x0000)
+            }]
+        );
+
+        let runs_off = lint(&[0x60, 0x01]);
+        assert_eq!(runs_off[0].kind, LintKind::RunsOffEnd);
+        assert_eq!(
+            runs_off[0].to_string(),
+            "0x0200: error: runs off the end of the ROM"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// Looking at ROMs without running them
+pub mod graph;
+pub mod lint;
+
+use crate::chip8::{Instruction, MEM_SIZE, PROGRAM_START};
+
+// Where execution can go after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    // On to the next instruction
+    Next,
+    // On to the next instruction, or the one after it
+    Skip,
+    Jump(u16),
+    // Into a subroutine, then on to the next instruction once it returns
+    Call(u16),
+    Return,
+    // NNN plus V0, which can't be known without running the ROM
+    Computed(u16),
+}
+
+impl Flow {
+    pub fn of(instruction: &Instruction) -> Flow {
+        match *instruction {
+            Instruction::Jump(addr) => Flow::Jump(addr),
+            Instruction::SubroutineCall(addr) => Flow::Call(addr),
+            Instruction::SubroutineReturn() => Flow::Return,
+            Instruction::JumpV0(addr) => Flow::Computed(addr),
+            Instruction::SkipIfEqualImm(..)
+            | Instruction::SkipIfNotEqualImm(..)
+            | Instruction::SkipIfEqualReg(..)
+            | Instruction::SkipIfNotEqualReg(..)
+            | Instruction::SkipIfPressed(_)
+            | Instruction::SkipIfNotPressed(_) => Flow::Skip,
+            _ => Flow::Next,
+        }
+    }
+}
+
+// A ROM as it sits in memory
+#[derive(Clone, Copy)]
+pub struct Image<'a> {
+    rom: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    pub fn new(rom: &'a [u8]) -> Image<'a> {
+        Image { rom }
+    }
+
+    // Where the ROM is loaded and starts running
+    pub fn start(&self) -> u16 {
+        PROGRAM_START as u16
+    }
+
+    // Address just past the last byte of the ROM
+    pub fn end(&self) -> u16 {
+        (PROGRAM_START + self.rom.len()).min(MEM_SIZE) as u16
+    }
+
+    // Whether a whole instruction at `addr` is part of the ROM
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.start() && addr as usize + 2 <= self.end() as usize
+    }
+
+    pub fn opcode(&self, addr: u16) -> Option<u16> {
+        if !self.contains(addr) {
+            return None;
+        }
+
+        let offset = (addr - self.start()) as usize;
+        Some(u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]]))
+    }
+}
@@ -0,0 +1,454 @@
+// Compatibility report for a directory of ROMs.
+//
+//   chip8-compat [options] [dir]
+//
+// Every .ch8 file under the directory (static/roms by default) is run
+// headlessly, without input, once per quirk profile. The report says how each
+// run ended and which opcodes the ROM executed, as a Markdown table or JSON.
+use chip_8::chip8::{guess_quirks, Chip8Error, Instruction, Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-compat [options] [dir]
+
+Runs every .ch8 file under dir (default static/roms) and reports how it did.
+
+Options:
+  --frames <n>       Frames to run each ROM for (default 600)
+  --speed <n>        Instructions per frame (default 9)
+  --profile <list>   Quirk profile to run, a comma separated list of quirks,
+                     \"none\", or \"auto\" to guess them for each ROM. Can be
+                     repeated (default: none and display_wait)
+  --json             Write JSON instead of Markdown
+  -h, --help         Show this message";
+
+// Runs are repeatable, but the numbers still have to come from somewhere
+const SEED: u64 = 0;
+
+struct Args {
+    dir: PathBuf,
+    frames: u64,
+    speed: usize,
+    // `None` guesses the quirks from each ROM
+    profiles: Vec<(String, Option<Quirks>)>,
+    json: bool,
+}
+
+fn parse_profile(profile: &str) -> Result<(String, Option<Quirks>), ()> {
+    match profile {
+        "none" => Ok(("none".to_string(), Some(Quirks::default()))),
+        "auto" => Ok(("auto".to_string(), None)),
+        _ => Ok((profile.to_string(), Some(profile.parse()?))),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        dir: PathBuf::from("static/roms"),
+        frames: 600,
+        speed: STEPS_PER_FRAME,
+        profiles: Vec::new(),
+        json: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--json" => {
+                parsed.json = true;
+                continue;
+            }
+            _ if !arg.starts_with("--") => {
+                parsed.dir = PathBuf::from(arg);
+                continue;
+            }
+            _ => {}
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+
+        match arg.as_str() {
+            "--frames" => parsed.frames = value.parse().map_err(|_| invalid())?,
+            "--speed" => parsed.speed = value.parse().map_err(|_| invalid())?,
+            "--profile" => parsed
+                .profiles
+                .push(parse_profile(&value).map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    if parsed.profiles.is_empty() {
+        parsed.profiles = vec![
+            parse_profile("none").unwrap(),
+            parse_profile("display_wait").unwrap(),
+        ];
+    }
+
+    Ok(parsed)
+}
+
+// How a run ended
+enum Outcome {
+    // Ran the whole budget and drew something
+    Ok,
+    // Ran the whole budget but never drew anything, or ended up in an
+    // instruction that jumps to itself
+    Idle(Option<u16>),
+    Unimplemented(Instruction),
+    Error(Chip8Error),
+    Panicked(String),
+}
+
+impl Outcome {
+    fn status(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Idle(_) => "idle",
+            Outcome::Unimplemented(_) => "unimplemented",
+            Outcome::Error(_) => "error",
+            Outcome::Panicked(_) => "panic",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            Outcome::Ok | Outcome::Idle(None) => None,
+            Outcome::Idle(Some(addr)) => Some(format!("jumps to itself at {:#05X}", addr)),
+            Outcome::Unimplemented(instruction) => {
+                Some(format!("{} ({})", instruction, instruction.pattern()))
+            }
+            Outcome::Error(e) => Some(e.to_string()),
+            Outcome::Panicked(message) => Some(message.clone()),
+        }
+    }
+}
+
+struct Run {
+    outcome: Outcome,
+    frames: u64,
+
+    // Frames on which the screen changed
+    frames_rendered: u64,
+    waiting_for_key: bool,
+}
+
+struct Report {
+    // Relative to the directory that was searched
+    name: String,
+    runs: Vec<Run>,
+
+    // Every opcode executed under any profile
+    opcodes: BTreeSet<&'static str>,
+}
+
+fn instruction_at(memory: &[u8], pc: u16) -> Option<Instruction> {
+    let pc = pc as usize;
+    let bytes = memory.get(pc..pc + 2)?;
+
+    Instruction::from_bytes([bytes[0], bytes[1]]).ok()
+}
+
+fn run_rom(rom: &[u8], quirks: Quirks, args: &Args, opcodes: &mut BTreeSet<&'static str>) -> Run {
+    let mut frames = 0;
+    let mut frames_rendered = 0;
+    let mut waiting_for_key = false;
+
+    // Drawing off the edge of the screen can panic, that shouldn't stop
+    // the rest of the report
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut headless = Headless::new(rom, quirks)?;
+        headless.chip8_mut().set_seed(SEED);
+
+        let mut last_screen = *headless.chip8().screen();
+        while headless.frame() < args.frames {
+            headless.run_frame_until(args.speed, |chip8| {
+                if !chip8.is_waiting_for_key() {
+                    if let Some(instruction) =
+                        instruction_at(chip8.memory(), chip8.program_counter())
+                    {
+                        opcodes.insert(instruction.pattern());
+                    }
+                }
+                false
+            })?;
+
+            frames = headless.frame();
+            if *headless.chip8().screen() != last_screen {
+                last_screen = *headless.chip8().screen();
+                frames_rendered += 1;
+            }
+            waiting_for_key = headless.chip8().is_waiting_for_key();
+        }
+
+        let chip8 = headless.chip8();
+        let pc = chip8.program_counter();
+        match instruction_at(chip8.memory(), pc) {
+            Some(Instruction::Jump(addr)) if addr == pc => Ok(Some(pc)),
+            _ => Ok(None),
+        }
+    }));
+
+    let outcome = match result {
+        Ok(Ok(Some(addr))) => Outcome::Idle(Some(addr)),
+        Ok(Ok(None)) if frames_rendered == 0 => Outcome::Idle(None),
+        Ok(Ok(None)) => Outcome::Ok,
+        Ok(Err(Chip8Error::InstructionNotImplemented(instruction))) => {
+            Outcome::Unimplemented(instruction)
+        }
+        Ok(Err(e)) => Outcome::Error(e),
+        Err(payload) => Outcome::Panicked(
+            payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        ),
+    };
+
+    Run {
+        outcome,
+        frames,
+        frames_rendered,
+        waiting_for_key,
+    }
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path.extension().is_some_and(|ext| ext == "ch8") {
+            roms.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn markdown(reports: &[Report], args: &Args) -> String {
+    let mut md = String::new();
+
+    writeln!(md, "# ROM compatibility\n").unwrap();
+    writeln!(
+        md,
+        "{} frames per ROM at {} instructions per frame, no input.\n",
+        args.frames, args.speed
+    )
+    .unwrap();
+
+    let names: Vec<&str> = args
+        .profiles
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    writeln!(md, "| ROM | {} |", names.join(" | ")).unwrap();
+    writeln!(md, "|---{}|", "|---".repeat(names.len())).unwrap();
+
+    for report in reports {
+        let cells: Vec<String> = report
+            .runs
+            .iter()
+            .map(|run| {
+                let mut cell = run.outcome.status().to_string();
+                if let Some(detail) = run.outcome.detail() {
+                    write!(cell, ": {}", detail).unwrap();
+                }
+                if run.frames < args.frames {
+                    write!(cell, ", stopped at frame {}", run.frames).unwrap();
+                } else {
+                    write!(cell, ", {} frames drawn", run.frames_rendered).unwrap();
+                }
+                if run.waiting_for_key {
+                    cell += ", waiting for key";
+                }
+                cell.replace('|', "\\|")
+            })
+            .collect();
+        writeln!(md, "| {} | {} |", report.name, cells.join(" | ")).unwrap();
+    }
+
+    writeln!(md, "\n## Opcodes used\n").unwrap();
+    writeln!(md, "| ROM | Opcodes |").unwrap();
+    writeln!(md, "|---|---|").unwrap();
+    for report in reports {
+        let opcodes: Vec<&str> = report.opcodes.iter().cloned().collect();
+        writeln!(md, "| {} | {} |", report.name, opcodes.join(" ")).unwrap();
+    }
+
+    md
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+fn json(reports: &[Report], args: &Args) -> String {
+    let profiles: Vec<String> = args
+        .profiles
+        .iter()
+        .map(|(name, _)| json_string(name))
+        .collect();
+
+    let roms: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let results: Vec<String> = args
+                .profiles
+                .iter()
+                .zip(report.runs.iter())
+                .map(|((name, _), run)| {
+                    let detail = run
+                        .outcome
+                        .detail()
+                        .map_or("null".to_string(), |detail| json_string(&detail));
+                    format!(
+                        "{}: {{\"status\": {}, \"detail\": {}, \"frames\": {}, \"frames_rendered\": {}, \"waiting_for_key\": {}}}",
+                        json_string(name),
+                        json_string(run.outcome.status()),
+                        detail,
+                        run.frames,
+                        run.frames_rendered,
+                        run.waiting_for_key
+                    )
+                })
+                .collect();
+            let opcodes: Vec<String> = report.opcodes.iter().map(|op| json_string(op)).collect();
+
+            format!(
+                "    {{\"rom\": {}, \"opcodes\": [{}], \"results\": {{{}}}}}",
+                json_string(&report.name),
+                opcodes.join(", "),
+                results.join(", ")
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"frames\": {},\n  \"speed\": {},\n  \"profiles\": [{}],\n  \"roms\": [\n{}\n  ]\n}}\n",
+        args.frames,
+        args.speed,
+        profiles.join(", "),
+        roms.join(",\n")
+    )
+}
+
+fn run(args: Args) -> Result<String, String> {
+    let mut roms = Vec::new();
+    find_roms(&args.dir, &mut roms)
+        .map_err(|e| format!("Could not search {}: {}", args.dir.display(), e))?;
+    roms.sort();
+
+    // Panics are part of the report, don't print them as well
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut reports = Vec::new();
+    for path in roms {
+        let rom =
+            fs::read(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+        let mut opcodes = BTreeSet::new();
+        let runs = args
+            .profiles
+            .iter()
+            .map(|(_, quirks)| {
+                let quirks = quirks.unwrap_or_else(|| guess_quirks(&rom).quirks);
+                run_rom(&rom, quirks, &args, &mut opcodes)
+            })
+            .collect();
+
+        let name = path.strip_prefix(&args.dir).unwrap_or(&path);
+        reports.push(Report {
+            name: name.with_extension("").display().to_string(),
+            runs,
+            opcodes,
+        });
+    }
+
+    let _ = panic::take_hook();
+
+    Ok(if args.json {
+        json(&reports, &args)
+    } else {
+        markdown(&reports, &args)
+    })
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(args) {
+        Ok(report) => print!("{}", report),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_bytes(rom: &[u8]) -> (Run, BTreeSet<&'static str>) {
+        let args = parse_args(["--frames", "10"].iter().map(|arg| arg.to_string())).unwrap();
+        let mut opcodes = BTreeSet::new();
+        let run = run_rom(rom, Quirks::default(), &args, &mut opcodes);
+
+        (run, opcodes)
+    }
+
+    #[test]
+    fn test_outcomes() {
+        // CLS, JP 0x202
+        let (run, opcodes) = run_bytes(&[0x00, 0xE0, 0x12, 0x02]);
+        assert!(matches!(run.outcome, Outcome::Idle(Some(0x202))));
+        assert_eq!(run.frames, 10);
+        assert_eq!(opcodes.into_iter().collect::<Vec<_>>(), ["00E0", "1NNN"]);
+
+        // SUBN V0, V1
+        let (run, opcodes) = run_bytes(&[0x80, 0x17]);
+        assert!(matches!(run.outcome, Outcome::Unimplemented(_)));
+        assert!(opcodes.contains("8XY7"));
+
+        let (run, _) = run_bytes(&[0xFF, 0xFF]);
+        assert_eq!(run.outcome.status(), "error");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("games/Pong \"1\""), "\"games/Pong \\\"1\\\"\"");
+        assert_eq!(json_string("a\\b\n"), "\"a\\\\b\\u000a\"");
+    }
+}
@@ -0,0 +1,105 @@
+// Draws the structure of a ROM, its basic blocks and subroutines, as a
+// Graphviz graph.
+//
+//   chip8-graph [options] <rom.ch8>
+//
+// The graph is written to stdout, or to a file with --output, ready for e.g.
+// `dot -Tsvg`.
+use chip_8::analysis::graph::Graph;
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-graph [options] <rom.ch8>
+
+Options:
+  --output <file>    Write the DOT graph to a file instead of stdout
+  -h, --help         Show this message";
+
+struct Args {
+    rom: String,
+    output: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut rom = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--output" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                output = Some(value);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => {
+                if rom.replace(arg).is_some() {
+                    return Err("Only one ROM can be drawn at a time".to_string());
+                }
+            }
+        }
+    }
+
+    Ok(Args {
+        rom: rom.ok_or_else(|| "No ROM given".to_string())?,
+        output,
+    })
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&args.rom) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {}", args.rom, e);
+            process::exit(2);
+        }
+    };
+    let dot = Graph::new(&rom).to_dot();
+
+    match args.output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, dot) {
+                eprintln!("Couldn't write {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", dot),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&["--output", "pong.dot", "pong.ch8"]).unwrap();
+        assert_eq!(parsed.rom, "pong.ch8");
+        assert_eq!(parsed.output.as_deref(), Some("pong.dot"));
+
+        assert_eq!(args(&["pong.ch8"]).unwrap().output, None);
+        assert!(args(&["--output"]).is_err());
+        assert!(args(&["one.ch8", "two.ch8"]).is_err());
+        assert!(args(&[]).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// Checks ROMs for mistakes without running them.
+//
+//   chip8-lint [options] <rom.ch8>...
+//
+// Prints a line for each problem found, e.g.
+//
+//   game.ch8: 0x0204: error: 0000 isn't an instruction
+//
+// and exits with 1 if any ROM has errors, so it can gate a build.
+use chip_8::analysis::lint::{lint, Severity};
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-lint [options] <rom.ch8>...
+
+Options:
+  --deny-warnings    Exit with 1 on warnings as well as errors
+  -h, --help         Show this message";
+
+struct Args {
+    roms: Vec<String>,
+    deny_warnings: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        roms: Vec::new(),
+        deny_warnings: false,
+    };
+
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--deny-warnings" => parsed.deny_warnings = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => parsed.roms.push(arg),
+        }
+    }
+
+    if parsed.roms.is_empty() {
+        return Err("No ROM given".to_string());
+    }
+
+    Ok(parsed)
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut failed = false;
+    for path in &args.roms {
+        let rom = match fs::read(path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                eprintln!("Couldn't read {}: {}", path, e);
+                process::exit(2);
+            }
+        };
+
+        for lint in lint(&rom) {
+            println!("{}: {}", path, lint);
+            failed |= args.deny_warnings || lint.severity() == Severity::Error;
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&["one.ch8", "--deny-warnings", "two.ch8"]).unwrap();
+        assert_eq!(parsed.roms, ["one.ch8", "two.ch8"]);
+        assert!(parsed.deny_warnings);
+
+        assert!(!args(&["one.ch8"]).unwrap().deny_warnings);
+        assert!(args(&["--strict", "one.ch8"]).is_err());
+        assert!(args(&[]).is_err());
+    }
+}
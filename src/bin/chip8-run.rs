@@ -0,0 +1,304 @@
+// Runs a ROM without a browser and prints where it ended up.
+//
+//   chip8-run [options] <rom.ch8>
+//
+// The machine runs for a number of frames, or until an instruction fails or
+// the program counter reaches a breakpoint. Then the screen and registers are
+// printed, so ROM checks can run anywhere cargo does.
+use chip_8::chip8::{Chip8, Chip8Error, Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+use chip_8::png::encode_grid;
+use chip_8::screen::{grid_to_text, Palette, MAX_SCALE};
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: chip8-run [options] <rom.ch8>
+
+Options:
+  --frames <n>       Number of 60Hz frames to run (default 600)
+  --speed <n>        Instructions per frame (default 9)
+  --quirks <list>    Comma separated quirks to turn on, e.g. display_wait
+  --seed <n>         Seed for random numbers (default 0)
+  --keys <file>      Key script, lines of \"<frame> <key>\" with key 0-F or - for none
+  --break <addr>     Stop when the program counter reaches addr (hex), can be repeated
+  --png <file>       Write the final screen to a PNG instead of printing it
+  --scale <n>        Pixels per Chip-8 pixel in the PNG, 1 to 64 (default 10)
+  --palette <name>   Palette preset or colours for the PNG (default high-contrast)
+  -h, --help         Show this message";
+
+struct Args {
+    rom: String,
+    frames: u64,
+    speed: usize,
+    quirks: Quirks,
+    seed: u64,
+    keys: Option<String>,
+    breakpoints: Vec<u16>,
+    png: Option<String>,
+    scale: usize,
+    palette: Palette,
+}
+
+// Why the run ended
+enum Stop {
+    Finished,
+    Breakpoint(u16),
+    Error(Chip8Error),
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        rom: String::new(),
+        frames: 600,
+        speed: STEPS_PER_FRAME,
+        quirks: Quirks::default(),
+        seed: 0,
+        keys: None,
+        breakpoints: Vec::new(),
+        png: None,
+        scale: 10,
+        palette: Palette::preset("high-contrast").unwrap_or_default(),
+    };
+    let mut rom = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+
+        if !arg.starts_with("--") {
+            if rom.replace(arg).is_some() {
+                return Err("Only one ROM can be run at a time".to_string());
+            }
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+
+        match arg.as_str() {
+            "--frames" => parsed.frames = value.parse().map_err(|_| invalid())?,
+            "--speed" => parsed.speed = value.parse().map_err(|_| invalid())?,
+            "--quirks" => parsed.quirks = value.parse().map_err(|_| invalid())?,
+            "--seed" => parsed.seed = value.parse().map_err(|_| invalid())?,
+            "--keys" => parsed.keys = Some(value),
+            "--break" => {
+                let addr = value.trim_start_matches("0x").trim_start_matches("0X");
+                let addr = u16::from_str_radix(addr, 16).map_err(|_| invalid())?;
+                parsed.breakpoints.push(addr);
+            }
+            "--png" => parsed.png = Some(value),
+            "--scale" => {
+                parsed.scale = match value.parse() {
+                    Ok(scale) if (1..=MAX_SCALE).contains(&scale) => scale,
+                    _ => return Err(invalid()),
+                }
+            }
+            "--palette" => parsed.palette = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    parsed.rom = rom.ok_or_else(|| "No ROM given".to_string())?;
+
+    Ok(parsed)
+}
+
+// Keys to hold down from a frame on, in frame order
+fn parse_key_script(script: &str) -> Result<Vec<(u64, Option<u8>)>, String> {
+    let mut keys: Vec<(u64, Option<u8>)> = Vec::new();
+
+    for (number, line) in script.lines().enumerate() {
+        // '#' starts a comment
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || format!("Invalid key script at line {}: {}", number + 1, line);
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (frame, key) = match words[..] {
+            [frame, key] => (frame, key),
+            _ => return Err(invalid()),
+        };
+
+        let frame: u64 = frame.parse().map_err(|_| invalid())?;
+        let key = match key {
+            "-" => None,
+            _ => match u8::from_str_radix(key, 16) {
+                Ok(key) if key <= 0xF => Some(key),
+                _ => return Err(invalid()),
+            },
+        };
+
+        if keys.last().is_some_and(|(last, _)| frame < *last) {
+            return Err(invalid());
+        }
+        keys.push((frame, key));
+    }
+
+    Ok(keys)
+}
+
+fn format_registers(chip8: &Chip8) -> String {
+    let mut text = String::new();
+
+    for (i, val) in chip8.registers().iter().enumerate() {
+        write!(text, "V{:X}: {:#04X}", i, val).unwrap();
+        text.push(if i % 4 == 3 { '\n' } else { ' ' });
+    }
+    writeln!(
+        text,
+        "I: {:#06X} PC: {:#06X} DT: {:#04X} ST: {:#04X}",
+        chip8.i_register(),
+        chip8.program_counter(),
+        chip8.delay_timer(),
+        chip8.sound_timer()
+    )
+    .unwrap();
+
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|addr| format!("{:#06X}", addr))
+        .collect();
+    writeln!(text, "Stack: [{}]", stack.join(", ")).unwrap();
+
+    text
+}
+
+fn run(args: Args) -> Result<bool, String> {
+    let rom = fs::read(&args.rom).map_err(|e| format!("Could not read {}: {}", args.rom, e))?;
+
+    let keys = match &args.keys {
+        Some(path) => {
+            let script =
+                fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+            parse_key_script(&script)?
+        }
+        None => Vec::new(),
+    };
+    let mut keys = keys.iter().peekable();
+
+    let mut headless = Headless::new(&rom, args.quirks).map_err(|e| e.to_string())?;
+    headless.chip8_mut().set_seed(args.seed);
+
+    let mut stop = Stop::Finished;
+    while headless.frame() < args.frames {
+        while let Some((_, key)) = keys.next_if(|(frame, _)| *frame <= headless.frame()) {
+            headless.set_key(*key);
+        }
+
+        let breakpoints = &args.breakpoints;
+        match headless.run_frame_until(args.speed, |chip8| {
+            breakpoints.contains(&chip8.program_counter())
+        }) {
+            Ok(false) => {}
+            Ok(true) => {
+                stop = Stop::Breakpoint(headless.chip8().program_counter());
+                break;
+            }
+            Err(e) => {
+                stop = Stop::Error(e);
+                break;
+            }
+        }
+    }
+
+    let chip8 = headless.chip8();
+    match &args.png {
+        Some(path) => {
+            let png = encode_grid(chip8.screen(), args.scale, &args.palette)?;
+            fs::write(path, png).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        }
+        None => print!("{}", grid_to_text(chip8.screen())),
+    }
+
+    println!("Frame: {}", headless.frame());
+    print!("{}", format_registers(chip8));
+
+    match stop {
+        Stop::Finished => Ok(true),
+        Stop::Breakpoint(addr) => {
+            println!("Breakpoint at {:#06X}", addr);
+            Ok(true)
+        }
+        Stop::Error(e) => {
+            println!("Error: {}", e);
+            Ok(false)
+        }
+    }
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[
+            "--frames", "10", "rom.ch8", "--break", "0x2A8", "--break", "300",
+        ])
+        .unwrap();
+        assert_eq!(parsed.rom, "rom.ch8");
+        assert_eq!(parsed.frames, 10);
+        assert_eq!(parsed.breakpoints, [0x2A8, 0x300]);
+
+        assert!(
+            args(&["--quirks", "display_wait", "rom.ch8"])
+                .unwrap()
+                .quirks
+                .display_wait
+        );
+        assert!(args(&["--frames"]).is_err());
+        assert!(args(&["--frames", "ten", "rom.ch8"]).is_err());
+        assert!(args(&["one.ch8", "two.ch8"]).is_err());
+        assert!(args(&["--scale", "0", "rom.ch8"]).is_err());
+        assert!(args(&["--scale", "100000", "rom.ch8"]).is_err());
+        assert!(args(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_key_script() {
+        let script = "# hold 5 then let go\n10 5\n\n20 - # released\n20 a\n";
+        assert_eq!(
+            parse_key_script(script),
+            Ok(vec![(10, Some(5)), (20, None), (20, Some(0xA))])
+        );
+
+        assert!(parse_key_script("10 G").is_err());
+        assert!(parse_key_script("10 10").is_err());
+        assert!(parse_key_script("20 1\n10 2").is_err());
+    }
+}
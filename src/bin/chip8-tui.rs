@@ -0,0 +1,398 @@
+// Play a ROM in the terminal.
+//
+//   chip8-tui [options] <rom.ch8>
+//
+// The screen is drawn with half block characters, two Chip-8 rows to a line,
+// with the registers alongside. Keys use the same layout as the browser:
+//
+//   1 2 3 4        1 2 3 C
+//   Q W E R   ->   4 5 6 D
+//   A S D F        7 8 9 E
+//   Z X C V        A 0 B F
+//
+// Esc quits, Space pauses and N runs a single frame while paused.
+use chip_8::chip8::{Chip8, Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+use chip_8::screen::{RawGrid, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::{cursor, queue, terminal};
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: chip8-tui [options] <rom.ch8>
+
+Options:
+  --speed <n>        Instructions per frame (default 9)
+  --quirks <list>    Comma separated quirks to turn on, e.g. display_wait
+  --seed <n>         Seed for random numbers (default random)
+  -h, --help         Show this message";
+
+const FRAME_PERIOD: Duration = Duration::from_micros(1_000_000 / 60);
+
+// Same layout as js/keyboard.ts
+#[rustfmt::skip]
+const KEY_MAP: [(char, u8); 16] = [
+    ('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC),
+    ('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD),
+    ('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE),
+    ('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF),
+];
+
+// Most terminals only report key presses, never releases. A key is held
+// for a while after it is pressed, long enough to bridge the delay before
+// the terminal starts repeating it, then only until the next repeat.
+const FIRST_HOLD_FRAMES: u64 = 36;
+const REPEAT_HOLD_FRAMES: u64 = 6;
+
+struct Args {
+    rom: String,
+    speed: usize,
+    quirks: Quirks,
+    seed: Option<u64>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut speed = STEPS_PER_FRAME;
+    let mut quirks = Quirks::default();
+    let mut seed = None;
+    let mut rom = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+
+        if !arg.starts_with("--") {
+            if rom.replace(arg).is_some() {
+                return Err("Only one ROM can be run at a time".to_string());
+            }
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg))?;
+        let invalid = || format!("Invalid value for {}: {}", arg, value);
+
+        match arg.as_str() {
+            "--speed" => speed = value.parse().map_err(|_| invalid())?,
+            "--quirks" => quirks = value.parse().map_err(|_| invalid())?,
+            "--seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    Ok(Args {
+        rom: rom.ok_or_else(|| "No ROM given".to_string())?,
+        speed,
+        quirks,
+        seed,
+    })
+}
+
+fn chip8_key(code: KeyCode) -> Option<u8> {
+    match code {
+        KeyCode::Char(c) => KEY_MAP
+            .iter()
+            .find(|(key, _)| *key == c.to_ascii_lowercase())
+            .map(|(_, chip8_key)| *chip8_key),
+        _ => None,
+    }
+}
+
+// Works out which key is down from the key events the terminal sends
+struct Keypad {
+    // Terminal sends proper release events
+    reports_release: bool,
+
+    key: Option<u8>,
+    release_at: u64,
+}
+
+impl Keypad {
+    fn event(&mut self, event: &KeyEvent, frame: u64) {
+        let key = match chip8_key(event.code) {
+            Some(key) => key,
+            None => return,
+        };
+
+        match event.kind {
+            KeyEventKind::Release => {
+                if self.key == Some(key) {
+                    self.key = None;
+                }
+            }
+            _ if self.reports_release => self.key = Some(key),
+            _ => {
+                let hold = if self.key == Some(key) {
+                    REPEAT_HOLD_FRAMES
+                } else {
+                    FIRST_HOLD_FRAMES
+                };
+                self.key = Some(key);
+                self.release_at = frame + hold;
+            }
+        }
+    }
+
+    fn pressed_key(&mut self, frame: u64) -> Option<u8> {
+        if !self.reports_release && frame >= self.release_at {
+            self.key = None;
+        }
+
+        self.key
+    }
+}
+
+// Puts the terminal back how it was, even if we panic
+struct TerminalGuard {
+    enhanced_keyboard: bool,
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+
+        let mut stdout = io::stdout();
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keyboard {
+            queue!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        stdout.flush()?;
+
+        Ok(TerminalGuard { enhanced_keyboard })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced_keyboard {
+            let _ = queue!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Two rows of pixels per line of text
+fn screen_lines(grid: &RawGrid) -> Vec<String> {
+    grid.chunks(2)
+        .map(|rows| {
+            let (top, bottom) = (rows[0], rows.get(1).copied().unwrap_or(0));
+            (0..SCREEN_WIDTH)
+                .map(|x| match ((top >> x) & 0x01, (bottom >> x) & 0x01) {
+                    (0, 0) => ' ',
+                    (1, 0) => '▀',
+                    (0, _) => '▄',
+                    _ => '█',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn sidebar_lines(chip8: &Chip8, frame: u64, key: Option<u8>, status: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for (i, pair) in chip8.registers().chunks(2).enumerate() {
+        lines.push(format!(
+            "V{:X}: {:02X}  V{:X}: {:02X}",
+            i * 2,
+            pair[0],
+            i * 2 + 1,
+            pair[1]
+        ));
+    }
+    lines.push(format!(
+        "I:  {:04X}  PC: {:04X}",
+        chip8.i_register(),
+        chip8.program_counter()
+    ));
+    lines.push(format!(
+        "DT: {:02X}    ST: {:02X}",
+        chip8.delay_timer(),
+        chip8.sound_timer()
+    ));
+
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|addr| format!("{:03X}", addr))
+        .collect();
+    lines.push(format!("Stack: {}", stack.join(" ")));
+
+    let key = key.map_or("-".to_string(), |key| format!("{:X}", key));
+    lines.push(format!("Frame: {}  Key: {}", frame, key));
+    lines.push(status.to_string());
+
+    lines
+}
+
+fn draw(
+    out: &mut impl Write,
+    headless: &Headless,
+    key: Option<u8>,
+    status: &str,
+) -> io::Result<()> {
+    let screen = screen_lines(headless.chip8().screen());
+    let sidebar = sidebar_lines(headless.chip8(), headless.frame(), key, status);
+
+    // Box around the screen, sidebar to the right of it
+    let border = "─".repeat(SCREEN_WIDTH);
+    queue!(out, cursor::MoveTo(0, 0), Print(format!("┌{}┐", border)))?;
+    for (row, line) in screen.iter().enumerate() {
+        let side = sidebar.get(row).map_or("", |side| side.as_str());
+        queue!(
+            out,
+            cursor::MoveTo(0, row as u16 + 1),
+            Print(format!("│{}│ {}", line, side)),
+            terminal::Clear(terminal::ClearType::UntilNewLine)
+        )?;
+    }
+    queue!(
+        out,
+        cursor::MoveTo(0, SCREEN_HEIGHT as u16 / 2 + 1),
+        Print(format!("└{}┘", border)),
+        cursor::MoveTo(0, SCREEN_HEIGHT as u16 / 2 + 2),
+        Print("Esc quit  Space pause  N step frame")
+    )?;
+
+    out.flush()
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let rom = fs::read(&args.rom).map_err(|e| format!("Could not read {}: {}", args.rom, e))?;
+
+    let mut headless = Headless::new(&rom, args.quirks).map_err(|e| e.to_string())?;
+    if let Some(seed) = args.seed {
+        headless.chip8_mut().set_seed(seed);
+    }
+
+    let guard = TerminalGuard::enter().map_err(|e| e.to_string())?;
+    let mut keypad = Keypad {
+        reports_release: guard.enhanced_keyboard,
+        key: None,
+        release_at: 0,
+    };
+
+    let mut stdout = io::stdout();
+    let mut paused = false;
+    let mut error = None;
+    let mut next_frame = Instant::now();
+
+    loop {
+        let mut step = false;
+        while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+            if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                let pressed = key.kind != KeyEventKind::Release;
+                match key.code {
+                    KeyCode::Esc if pressed => return Ok(()),
+                    KeyCode::Char('c')
+                        if pressed && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        return Ok(())
+                    }
+                    KeyCode::Char(' ') if pressed => paused = !paused,
+                    KeyCode::Char('n') if pressed && paused => step = true,
+                    _ => keypad.event(&key, headless.frame()),
+                }
+            }
+        }
+
+        let key = keypad.pressed_key(headless.frame());
+        if (!paused || step) && error.is_none() {
+            headless.set_key(key);
+            if let Err(e) = headless.run_frame(args.speed) {
+                error = Some(e.to_string());
+            }
+        }
+
+        let status = match &error {
+            Some(e) => format!("Error: {}", e),
+            None if paused => "Paused".to_string(),
+            None => "Running".to_string(),
+        };
+        draw(&mut stdout, &headless, key, &status).map_err(|e| e.to_string())?;
+
+        // Keep to 60 frames a second, without trying to catch up after a stall
+        next_frame += FRAME_PERIOD;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_screen_lines() {
+        let mut grid: RawGrid = [0; SCREEN_HEIGHT];
+        grid[0] = 0b0011;
+        grid[1] = 0b0101;
+
+        let lines = screen_lines(&grid);
+        assert_eq!(lines.len(), SCREEN_HEIGHT / 2);
+        assert!(lines[0].starts_with("█▀▄ "));
+        assert_eq!(lines[1].trim(), "");
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let mut keypad = Keypad {
+            reports_release: false,
+            key: None,
+            release_at: 0,
+        };
+        let press = KeyEvent::new(KeyCode::Char('W'), KeyModifiers::NONE);
+
+        keypad.event(&press, 0);
+        assert_eq!(keypad.pressed_key(FIRST_HOLD_FRAMES - 1), Some(0x5));
+
+        // Repeats keep it held for a shorter time
+        keypad.event(&press, FIRST_HOLD_FRAMES - 1);
+        assert_eq!(keypad.pressed_key(FIRST_HOLD_FRAMES), Some(0x5));
+        assert_eq!(
+            keypad.pressed_key(FIRST_HOLD_FRAMES - 1 + REPEAT_HOLD_FRAMES),
+            None
+        );
+    }
+}
@@ -1,7 +1,13 @@
+use super::detect::guess_quirks;
 use super::instructions::Instruction;
+use super::quirks::Quirks;
+use super::rng::Rng;
 use super::traits::{Drawable, HexKeyboard, Timer};
 use super::Chip8Error;
+use crate::screen::{RawGrid, SCREEN_HEIGHT};
+use std::collections::VecDeque;
 use std::convert::TryInto;
+use std::ops::Range;
 
 use rand::random;
 
@@ -32,16 +38,165 @@ pub fn character_offset(character: u8) -> u16 {
 }
 
 // Address where fonts will be loaded to
-const FONT_START: usize = 0x000;
+pub const FONT_START: usize = 0x000;
 
 // Chip-8 programs get loaded into memory starting at 0x200
 // everything below that is reserved for the system.
-const PROGRAM_START: usize = 0x200;
+pub const PROGRAM_START: usize = 0x200;
 
-const MEM_SIZE: usize = 0xFFF + 1;
+// Assuming a target speed of ~500Hz we have to step execution 500/60 = ~8 times
+// every 60Hz frame
+pub const STEPS_PER_FRAME: usize = 9;
+
+pub const MEM_SIZE: usize = 0xFFF + 1;
 const V_REG_SIZE: usize = 0xF + 1;
 const STACK_SIZE: usize = 0xF + 1;
 
+// Everything about a running program that can change, taken with
+// `Chip8::snapshot` and put back with `Chip8::restore`. Quirks and the
+// peripherals aren't part of it.
+#[derive(Clone)]
+pub struct Snapshot {
+    mem: [u8; MEM_SIZE],
+    v_reg: [u8; V_REG_SIZE],
+    i_reg: u16,
+    delay_reg: u8,
+    sound_reg: u8,
+    program_counter: u16,
+    stack: Vec<u16>,
+    waiting_for_key: bool,
+    key_reg: usize,
+    polled_keys: u16,
+    rng: Rng,
+    waiting_for_vblank: bool,
+    screen: RawGrid,
+}
+
+// Reads the fields of a saved snapshot in order
+struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn screen(&self) -> &RawGrid {
+        &self.screen
+    }
+
+    // Big endian fields in the order they're declared, the stack and
+    // random number generator written out as (length, entries) and
+    // (seed, state). Booleans are a byte. `None` if the snapshot holds
+    // something `from_bytes` wouldn't accept, rather than writing part of it.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        if self.stack.len() > STACK_SIZE || self.key_reg >= V_REG_SIZE {
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(MEM_SIZE + 512);
+
+        bytes.extend_from_slice(&self.mem);
+        bytes.extend_from_slice(&self.v_reg);
+        bytes.extend_from_slice(&self.i_reg.to_be_bytes());
+        bytes.push(self.delay_reg);
+        bytes.push(self.sound_reg);
+        bytes.extend_from_slice(&self.program_counter.to_be_bytes());
+        bytes.extend_from_slice(&(self.stack.len() as u16).to_be_bytes());
+        for addr in &self.stack {
+            bytes.extend_from_slice(&addr.to_be_bytes());
+        }
+        bytes.push(self.waiting_for_key as u8);
+        bytes.push(self.key_reg as u8);
+        bytes.extend_from_slice(&self.polled_keys.to_be_bytes());
+        bytes.extend_from_slice(&self.rng.seed().to_be_bytes());
+        bytes.extend_from_slice(&self.rng.state().to_be_bytes());
+        bytes.push(self.waiting_for_vblank as u8);
+        for row in &self.screen {
+            bytes.extend_from_slice(&row.to_be_bytes());
+        }
+
+        Some(bytes)
+    }
+
+    // `None` if the bytes aren't a snapshot written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Option<Snapshot> {
+        let mut reader = SnapshotReader { bytes };
+
+        let mem = reader.take(MEM_SIZE)?.try_into().ok()?;
+        let v_reg = reader.take(V_REG_SIZE)?.try_into().ok()?;
+        let i_reg = reader.u16()?;
+        let delay_reg = reader.u8()?;
+        let sound_reg = reader.u8()?;
+        let program_counter = reader.u16()?;
+        let stack_len = reader.u16()?;
+        if stack_len as usize > STACK_SIZE {
+            return None;
+        }
+        let stack = (0..stack_len)
+            .map(|_| reader.u16())
+            .collect::<Option<Vec<u16>>>()?;
+        let waiting_for_key = reader.bool()?;
+        let key_reg = reader.u8()? as usize;
+        let polled_keys = reader.u16()?;
+        let rng = Rng::from_parts(reader.u64()?, reader.u64()?)?;
+        let waiting_for_vblank = reader.bool()?;
+        let mut screen = [0; SCREEN_HEIGHT];
+        for row in screen.iter_mut() {
+            *row = reader.u64()?;
+        }
+
+        if key_reg >= V_REG_SIZE || !reader.bytes.is_empty() {
+            return None;
+        }
+
+        Some(Snapshot {
+            mem,
+            v_reg,
+            i_reg,
+            delay_reg,
+            sound_reg,
+            program_counter,
+            stack,
+            waiting_for_key,
+            key_reg,
+            polled_keys,
+            rng,
+            waiting_for_vblank,
+            screen,
+        })
+    }
+}
+
 pub struct Chip8 {
     mem: [u8; MEM_SIZE],
 
@@ -60,6 +215,23 @@ pub struct Chip8 {
 
     waiting_for_key: bool,
     key_reg: usize,
+    // Bit per key the program has checked since reset, all of them once it
+    // has waited for any key
+    polled_keys: u16,
+
+    quirks: Quirks,
+    // Set quirks from the next ROM loaded instead, see `guess_quirks`
+    guess_quirks: bool,
+    rng: Rng,
+
+    // Drawing only changes the framebuffer, it is presented on the next vblank
+    frame_dirty: bool,
+    waiting_for_vblank: bool,
+
+    // (address, opcode) of the last instructions fetched, oldest first.
+    // Only kept when `set_trace_length` is used, for crash reports.
+    trace: VecDeque<(u16, u16)>,
+    trace_length: usize,
 }
 
 impl Chip8 {
@@ -86,10 +258,62 @@ impl Chip8 {
 
             waiting_for_key: false,
             key_reg: 0x00,
+            polled_keys: 0,
+
+            quirks: Quirks::default(),
+            guess_quirks: false,
+            rng: Rng::new(random()),
+
+            frame_dirty: false,
+            waiting_for_vblank: false,
+
+            trace: VecDeque::new(),
+            trace_length: 0,
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.guess_quirks = false;
+    }
+
+    // Quirks for the next ROM given to `init_memory` are guessed from its
+    // bytes, until quirks are set again
+    pub fn guess_quirks_on_load(&mut self) {
+        self.guess_quirks = true;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Random numbers are repeatable for a given seed, the default one is random
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    // Remember the last `length` instructions fetched, see `trace`
+    pub fn set_trace_length(&mut self, length: usize) {
+        self.trace_length = length;
+        while self.trace.len() > length {
+            self.trace.pop_front();
         }
     }
 
+    // (address, opcode) of recently fetched instructions, oldest first
+    pub fn trace(&self) -> impl Iterator<Item = &(u16, u16)> {
+        self.trace.iter()
+    }
+
     pub fn init_memory(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        if self.guess_quirks {
+            self.quirks = guess_quirks(rom).quirks;
+        }
+
         self.load_rom(FONT_START, &CHIP8_FONT)?;
 
         self.load_rom(PROGRAM_START, rom)?;
@@ -97,6 +321,118 @@ impl Chip8 {
         return Ok(());
     }
 
+    // Back to the power on state, ready for `init_memory`. Quirks, the seed
+    // and the peripherals are kept.
+    pub fn reset(&mut self) {
+        self.mem = [0; MEM_SIZE];
+        self.v_reg = [0; V_REG_SIZE];
+        self.i_reg = 0;
+        self.delay_reg = 0;
+        self.sound_reg = 0;
+
+        self.program_counter = PROGRAM_START as u16;
+        self.stack.clear();
+
+        self.waiting_for_key = false;
+        self.key_reg = 0x00;
+        self.polled_keys = 0;
+
+        // Start the random numbers over and drop any time that has passed
+        self.rng = Rng::new(self.rng.seed());
+        self.timer.cycles_passed();
+
+        self.screen.clear();
+        self.frame_dirty = true;
+        self.waiting_for_vblank = false;
+
+        self.trace.clear();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mem: self.mem,
+            v_reg: self.v_reg,
+            i_reg: self.i_reg,
+            delay_reg: self.delay_reg,
+            sound_reg: self.sound_reg,
+            program_counter: self.program_counter,
+            stack: self.stack.clone(),
+            waiting_for_key: self.waiting_for_key,
+            key_reg: self.key_reg,
+            polled_keys: self.polled_keys,
+            rng: self.rng,
+            waiting_for_vblank: self.waiting_for_vblank,
+            screen: *self.screen.grid(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.mem = snapshot.mem;
+        self.v_reg = snapshot.v_reg;
+        self.i_reg = snapshot.i_reg;
+        self.delay_reg = snapshot.delay_reg;
+        self.sound_reg = snapshot.sound_reg;
+        self.program_counter = snapshot.program_counter;
+        self.stack = snapshot.stack.clone();
+        self.waiting_for_key = snapshot.waiting_for_key;
+        self.key_reg = snapshot.key_reg;
+        self.polled_keys = snapshot.polled_keys;
+        self.rng = snapshot.rng;
+        self.waiting_for_vblank = snapshot.waiting_for_vblank;
+
+        // Time that passed before the restore doesn't count
+        self.timer.cycles_passed();
+
+        self.screen.set_grid(&snapshot.screen);
+        self.frame_dirty = true;
+    }
+
+    // Parts of the machine state that aren't the same in `other`, e.g. "V3",
+    // "memory at 0x0300" or "screen". Empty when both are in the same state.
+    pub fn differences(&self, other: &Chip8) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        for (i, (a, b)) in self.v_reg.iter().zip(other.v_reg.iter()).enumerate() {
+            if a != b {
+                differences.push(format!("V{:X}", i));
+            }
+        }
+
+        let parts = [
+            ("I", self.i_reg != other.i_reg),
+            ("DT", self.delay_reg != other.delay_reg),
+            ("ST", self.sound_reg != other.sound_reg),
+            ("PC", self.program_counter != other.program_counter),
+            ("stack", self.stack != other.stack),
+            (
+                "waiting for key",
+                self.waiting_for_key != other.waiting_for_key,
+            ),
+            (
+                "waiting for vblank",
+                self.waiting_for_vblank != other.waiting_for_vblank,
+            ),
+            ("random numbers", self.rng != other.rng),
+            ("screen", self.screen.grid() != other.screen.grid()),
+        ];
+        for (name, differs) in parts {
+            if differs {
+                differences.push(name.to_string());
+            }
+        }
+
+        let first_changed = self
+            .mem
+            .iter()
+            .zip(other.mem.iter())
+            .position(|(a, b)| a != b);
+        if let Some(addr) = first_changed {
+            differences.push(format!("memory at {:#06X}", addr));
+        }
+
+        differences
+    }
+
     fn load_rom(&mut self, start_address: usize, rom: &[u8]) -> Result<(), Chip8Error> {
         let end_address = start_address + rom.len();
         if end_address >= MEM_SIZE {
@@ -108,8 +444,99 @@ impl Chip8 {
         return Ok(());
     }
 
+    // Read only views into the machine state, used by the debugger
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    // Carry on from `addr`, e.g. past an instruction that failed
+    pub fn jump(&mut self, addr: u16) {
+        self.program_counter = addr;
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.v_reg
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_reg
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_reg
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    pub fn polled_keys(&self) -> u16 {
+        self.polled_keys
+    }
+
+    pub fn screen(&self) -> &RawGrid {
+        self.screen.grid()
+    }
+
+    // Run one frame worth of instructions then present the screen. This
+    // should be called by the host 60 times per second.
+    pub fn run_frame(&mut self, steps: usize) -> Result<(), Chip8Error> {
+        let result = (0..steps).try_for_each(|_| self.step_execution());
+        self.vblank();
+
+        result
+    }
+
+    // Called at the end of every 60Hz frame. The screen is flushed even if
+    // nothing was drawn so renderers can animate effects between frames.
+    pub fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
+
+        self.screen.flush();
+        self.frame_dirty = false;
+    }
+
+    // Show the framebuffer if anything was drawn since it was last presented
+    pub fn present(&mut self) {
+        if self.frame_dirty {
+            self.screen.flush();
+            self.frame_dirty = false;
+        }
+    }
+
+    // Run one instruction, for single stepping in a debugger. An instruction
+    // held up by display wait would never run, so the frame it's waiting on
+    // is ended first.
+    pub fn step_instruction(&mut self) -> Result<(), Chip8Error> {
+        if self.waiting_for_vblank {
+            self.vblank();
+        }
+
+        self.step_execution()
+    }
+
     pub fn step_execution(&mut self) -> Result<(), Chip8Error> {
         // todo: do some timer stuff
+        if self.waiting_for_vblank {
+            return Ok(());
+        }
+
         if self.waiting_for_key {
             match self.keyboard.pressed_key() {
                 Some(key) => {
@@ -129,6 +556,28 @@ impl Chip8 {
         return Ok(());
     }
 
+    // Value 8XY6 and 8XYE shift, see `Quirks::shift_vy`
+    fn shift_source(&self, vx: u8, vy: u8) -> u8 {
+        match self.quirks.shift_vy {
+            true => self.v_reg[vy as usize],
+            false => self.v_reg[vx as usize],
+        }
+    }
+
+    // After FX55 or FX65, see `Quirks::load_store_increment`
+    fn load_store_done(&mut self, vx: u8) {
+        if self.quirks.load_store_increment {
+            self.i_reg = self.i_reg.wrapping_add(vx as u16 + 1);
+        }
+    }
+
+    fn poll_key(&mut self, key: u8) {
+        // Registers above 0xF never match a key
+        if key <= 0xF {
+            self.polled_keys |= 1 << key;
+        }
+    }
+
     fn decrement_timers(&mut self) {
         let decrement_amount = self.timer.cycles_passed();
 
@@ -143,12 +592,36 @@ impl Chip8 {
         };
     }
 
+    // Memory from `start` to `start + len`, or an error if any of it is
+    // outside of the address space. Called while executing an instruction,
+    // so the program counter has already moved past it.
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if start + len > MEM_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds(
+                start as u16,
+                self.program_counter.wrapping_sub(2),
+            ));
+        }
+
+        Ok(start..start + len)
+    }
+
     fn next_instruction(&mut self) -> Result<Instruction, Chip8Error> {
         let pc = self.program_counter as usize;
+        if pc + 2 > MEM_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds(pc as u16, pc as u16));
+        }
 
         let bytes: [u8; 2] = self.mem[pc..pc + 2].try_into().unwrap();
         self.program_counter += 2;
 
+        if self.trace_length > 0 {
+            if self.trace.len() == self.trace_length {
+                self.trace.pop_front();
+            }
+            self.trace.push_back((pc as u16, u16::from_be_bytes(bytes)));
+        }
+
         return match Instruction::from_bytes(bytes) {
             Ok(inst) => Ok(inst),
             Err(_) => Err(Chip8Error::InvalidInstruction(
@@ -194,6 +667,11 @@ impl Chip8 {
                 Ok(())
             }
             Instruction::SubroutineCall(addr) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow(
+                        self.program_counter.wrapping_sub(2),
+                    ));
+                }
                 self.stack.push(self.program_counter);
 
                 self.program_counter = addr;
@@ -209,6 +687,7 @@ impl Chip8 {
             }
             Instruction::SkipIfPressed(vx) => {
                 let key = self.v_reg[vx as usize];
+                self.poll_key(key);
                 if self.keyboard.pressed_key() == Some(key) {
                     self.program_counter += 2
                 }
@@ -217,6 +696,7 @@ impl Chip8 {
             }
             Instruction::SkipIfNotPressed(vx) => {
                 let key = self.v_reg[vx as usize];
+                self.poll_key(key);
                 if self.keyboard.pressed_key() != Some(key) {
                     self.program_counter += 2
                 }
@@ -255,28 +735,24 @@ impl Chip8 {
             }
             Instruction::LoadMemoryRegisters(vx) => {
                 // Dumps registers v0..vx to memory starting at i
-                let regs = &self.v_reg[..=vx as usize];
-
-                let reg_start = self.i_reg as usize;
-                let reg_end = reg_start + (vx as usize);
-                self.mem[reg_start..=reg_end].clone_from_slice(regs);
+                let range = self.memory_range(self.i_reg as usize, vx as usize + 1)?;
+                self.mem[range].clone_from_slice(&self.v_reg[..=vx as usize]);
+                self.load_store_done(vx);
 
                 Ok(())
             }
             Instruction::LoadRegistersMemory(vx) => {
                 // Loads registers v0..vx from memory starting at i
-                let reg_start = self.i_reg as usize;
-                let reg_end = reg_start + (vx as usize);
-                let regs = &self.mem[reg_start..=reg_end];
-
-                self.v_reg[..=vx as usize].clone_from_slice(regs);
+                let range = self.memory_range(self.i_reg as usize, vx as usize + 1)?;
+                self.v_reg[..=vx as usize].clone_from_slice(&self.mem[range]);
+                self.load_store_done(vx);
 
                 Ok(())
             }
             Instruction::LoadMemoryBcd(vx) => {
                 let val = self.v_reg[vx as usize];
 
-                let bcd_start = self.i_reg as usize;
+                let bcd_start = self.memory_range(self.i_reg as usize, 3)?.start;
                 self.mem[bcd_start + 2] = val % 10; // hundreds
                 self.mem[bcd_start + 1] = (val / 10) % 10; // tens
                 self.mem[bcd_start + 0] = (val / 100) % 10; // ones
@@ -290,6 +766,7 @@ impl Chip8 {
                 Ok(())
             }
             Instruction::LoadKey(vx) => {
+                self.polled_keys = 0xFFFF;
                 self.waiting_for_key = true;
                 self.key_reg = vx as usize;
 
@@ -301,20 +778,20 @@ impl Chip8 {
                 let x = self.v_reg[vx as usize] as usize;
                 let y = self.v_reg[vy as usize] as usize;
 
-                let sprite_start = self.i_reg as usize;
-                let sprite_end = sprite_start + (sprite_size as usize);
-                let sprite = &self.mem[sprite_start..sprite_end];
+                let range = self.memory_range(self.i_reg as usize, sprite_size as usize)?;
+                let sprite = &self.mem[range];
 
                 let did_collide = self.screen.write_sprite(x, y, sprite);
                 self.v_reg[0xF] = did_collide as u8;
 
-                self.screen.flush();
+                self.frame_dirty = true;
+                self.waiting_for_vblank = self.quirks.display_wait;
 
                 Ok(())
             }
             Instruction::ClearScreen() => {
                 self.screen.clear();
-                self.screen.flush();
+                self.frame_dirty = true;
 
                 Ok(())
             }
@@ -338,7 +815,7 @@ impl Chip8 {
                 Ok(())
             }
             Instruction::AddAddress(vx) => {
-                self.i_reg += self.v_reg[vx as usize] as u16;
+                self.i_reg = self.i_reg.wrapping_add(self.v_reg[vx as usize] as u16);
 
                 Ok(())
             }
@@ -353,7 +830,7 @@ impl Chip8 {
                 Ok(())
             }
             Instruction::Random(vx, byte) => {
-                self.v_reg[vx as usize] = random::<u8>() & byte;
+                self.v_reg[vx as usize] = self.rng.next_u8() & byte;
                 Ok(())
             }
 
@@ -373,8 +850,8 @@ impl Chip8 {
 
                 Ok(())
             }
-            Instruction::ShiftLeft(vx, _) => {
-                let x = self.v_reg[vx as usize];
+            Instruction::ShiftLeft(vx, vy) => {
+                let x = self.shift_source(vx, vy);
 
                 self.v_reg[0xF] = x & 0b0000_0001;
 
@@ -382,8 +859,8 @@ impl Chip8 {
 
                 Ok(())
             }
-            Instruction::ShiftRight(vx, _) => {
-                let x = self.v_reg[vx as usize];
+            Instruction::ShiftRight(vx, vy) => {
+                let x = self.shift_source(vx, vy);
 
                 self.v_reg[0xF] = (x & 0b1000_0000) >> 7;
 
@@ -395,3 +872,120 @@ impl Chip8 {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct NoScreen(RawGrid);
+
+    impl Drawable for NoScreen {
+        fn write_sprite(&mut self, _x: usize, _y: usize, _sprite: &[u8]) -> bool {
+            false
+        }
+
+        fn grid(&self) -> &RawGrid {
+            &self.0
+        }
+
+        fn flush(&mut self) {}
+
+        fn clear(&mut self) {}
+
+        fn set_grid(&mut self, grid: &RawGrid) {
+            self.0 = *grid;
+        }
+    }
+
+    struct NoKeys;
+
+    impl HexKeyboard for NoKeys {
+        fn pressed_key(&self) -> Option<u8> {
+            None
+        }
+    }
+
+    struct NoTime;
+
+    impl Timer for NoTime {
+        fn cycles_passed(&self) -> u8 {
+            0
+        }
+    }
+
+    #[test]
+    fn test_step_past_display_wait() {
+        // Draw, then set V0
+        let rom = [0xD0, 0x01, 0x60, 0x2A];
+        let mut chip8 = Chip8::new(
+            Box::new(NoScreen([0; 32])),
+            Box::new(NoKeys),
+            Box::new(NoTime),
+        );
+        chip8.set_quirks(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+        assert!(chip8.init_memory(&rom).is_ok());
+
+        assert!(chip8.step_instruction().is_ok());
+        assert!(chip8.waiting_for_vblank);
+
+        // Stepping doesn't get stuck on the frame the draw is waiting for
+        assert!(chip8.step_instruction().is_ok());
+        assert!(!chip8.waiting_for_vblank);
+        assert_eq!(chip8.program_counter(), 0x204);
+        assert_eq!(chip8.registers()[0], 0x2A);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // Call itself forever
+        let rom = [0x22, 0x00];
+        let mut chip8 = Chip8::new(
+            Box::new(NoScreen([0; 32])),
+            Box::new(NoKeys),
+            Box::new(NoTime),
+        );
+        chip8.init_memory(&rom).unwrap();
+
+        for _ in 0..STACK_SIZE {
+            chip8.step_execution().unwrap();
+        }
+        assert_eq!(chip8.stack().len(), STACK_SIZE);
+        assert!(matches!(
+            chip8.step_execution(),
+            Err(Chip8Error::StackOverflow(0x200))
+        ));
+        assert_eq!(chip8.stack().len(), STACK_SIZE);
+    }
+
+    #[test]
+    fn test_snapshot_bytes() {
+        let mut chip8 = Chip8::new(
+            Box::new(NoScreen([0; 32])),
+            Box::new(NoKeys),
+            Box::new(NoTime),
+        );
+        chip8.init_memory(&[0x22, 0x00]).unwrap();
+        for _ in 0..3 {
+            chip8.step_execution().unwrap();
+        }
+
+        let snapshot = chip8.snapshot();
+        let bytes = snapshot.to_bytes().unwrap();
+        let read = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(read.stack, [0x202; 3]);
+        assert_eq!(read.to_bytes().unwrap(), bytes);
+
+        // A longer stack than a Chip-8 has can't be written or read back
+        let mut deep = snapshot;
+        deep.stack = vec![0x200; STACK_SIZE + 1];
+        assert!(deep.to_bytes().is_none());
+
+        let len_at = MEM_SIZE + V_REG_SIZE + 6;
+        let mut bytes = bytes;
+        bytes[len_at..len_at + 2].copy_from_slice(&(STACK_SIZE as u16 + 1).to_be_bytes());
+        assert!(Snapshot::from_bytes(&bytes).is_none());
+    }
+}
@@ -0,0 +1,336 @@
+// Guesses the quirks a ROM was written for from its bytes alone, for ROMs
+// that don't say. Every two bytes are treated as an opcode, data included, so
+// each clue is only a hint and the guess comes with how sure it is.
+//
+// Clues are:
+// - Opcodes only SCHIP or XO-CHIP have, e.g. 00FF, F000 NNNN and 5XY2, which
+//   give the interpreter and so its quirks
+// - Shifts between two different registers, which only make sense if Vy is
+//   what gets shifted. Unless Vx is shifted again straight after, which
+//   only makes sense if Vx is.
+// - FX55 and FX65 run again without I being set in between, which only
+//   work if I moves past what was stored or loaded
+//
+// A quirk nothing was found for either way is left as `Quirks::default()`,
+// unless the ROM is known to be for SCHIP or XO-CHIP. Plain CHIP-8 ROMs
+// can't be told apart from the ones written for later interpreters, and
+// most of them run fine without quirks.
+use super::quirks::Quirks;
+use super::PROGRAM_START;
+
+use std::fmt;
+
+// Opcodes a clue has to be within to count, e.g. how far after a load to
+// look for the next one
+const LOOKAHEAD: usize = 8;
+
+// Share of the votes a quirk needs to be set against the default, as data
+// read as opcodes votes too
+const MIN_MAJORITY: f64 = 0.75;
+
+// Which interpreter a ROM was written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    // How the platform's interpreter behaved. Display wait can't be seen in
+    // the bytes and only slows programs down, so it's always left off.
+    fn quirks(self) -> Quirks {
+        let cosmac = self == Platform::Chip8 || self == Platform::XoChip;
+
+        Quirks {
+            display_wait: false,
+            shift_vy: cosmac,
+            load_store_increment: cosmac,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::SuperChip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuirkGuess {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // From 0.5, nothing to go on, to 1
+    pub confidence: f64,
+    // What the guess was based on, e.g. "00FF at 0x0234"
+    pub evidence: Vec<String>,
+}
+
+// "SCHIP (no quirks), 75% sure"
+impl fmt::Display for QuirkGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quirks = match self.quirks.to_string() {
+            quirks if quirks.is_empty() => String::from("no quirks"),
+            quirks => quirks,
+        };
+
+        write!(
+            f,
+            "{} ({}), {:.0}% sure",
+            self.platform,
+            quirks,
+            self.confidence * 100.0
+        )
+    }
+}
+
+// For and against a quirk
+#[derive(Default)]
+struct Votes {
+    yes: usize,
+    no: usize,
+}
+
+impl Votes {
+    // The quirk's setting and how sure that is, `default` when there
+    // wasn't a clear majority either way
+    fn decide(&self, default: (bool, f64)) -> (bool, f64) {
+        let total = self.yes + self.no;
+        if total == 0 {
+            return default;
+        }
+
+        let majority = self.yes.max(self.no) as f64 / total as f64;
+        match majority >= MIN_MAJORITY {
+            true => (self.yes > self.no, majority),
+            false => default,
+        }
+    }
+}
+
+pub fn guess_quirks(rom: &[u8]) -> QuirkGuess {
+    let opcodes: Vec<u16> = rom
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let address = |index: usize| PROGRAM_START + index * 2;
+
+    let mut evidence = Vec::new();
+    let mut schip: usize = 0;
+    let mut xo_chip: usize = 0;
+    let mut shift = Votes::default();
+    let mut load_store = Votes::default();
+
+    for (index, &opcode) in opcodes.iter().enumerate() {
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+
+        if let Some(platform) = platform_only(opcode) {
+            match platform {
+                Platform::XoChip => xo_chip += 1,
+                _ => schip += 1,
+            }
+            evidence.push(format!(
+                "{:04X} at {:#06X} is {} only",
+                opcode,
+                address(index),
+                platform
+            ));
+        }
+
+        let shifts_vx = |next: &u16| is_shift(*next) && (next >> 8 & 0xF) as usize == x;
+
+        match opcode & 0xF00F {
+            // e.g. three SHR V0, V5 in a row to divide V0 by 8
+            0x8006 | 0x800E if x != y && opcodes.get(index + 1).is_some_and(shifts_vx) => {
+                shift.no += 1
+            }
+            // Writing Vy as zero is how the Vx only shift is usually written
+            0x8006 | 0x800E if x != y && y != 0 => shift.yes += 1,
+            0x8006 | 0x800E if x != y => shift.no += 1,
+            _ => {}
+        }
+
+        if matches!(opcode & 0xF0FF, 0xF055 | 0xF065) {
+            match i_after_load_store(&opcodes, index) {
+                Some(true) => load_store.yes += 1,
+                Some(false) => load_store.no += 1,
+                None => {}
+            }
+        }
+    }
+
+    let (platform, found) = match (xo_chip, schip) {
+        (0, 0) => (Platform::Chip8, 0),
+        (0, schip) => (Platform::SuperChip, schip),
+        (xo_chip, schip) => (Platform::XoChip, xo_chip + schip),
+    };
+    // Every opcode found halves the chance they're all data
+    let platform_confidence = 1.0 - 0.5f64.powi(found as i32 + 1);
+    let defaults = match found {
+        0 => Quirks::default(),
+        _ => platform.quirks(),
+    };
+
+    let (shift_vy, shift_confidence) = shift.decide((defaults.shift_vy, platform_confidence));
+    if shift.yes + shift.no > 0 {
+        evidence.push(format!(
+            "{} shifts between two registers, {} of Vx alone",
+            shift.yes, shift.no
+        ));
+    }
+
+    let (load_store_increment, load_store_confidence) =
+        load_store.decide((defaults.load_store_increment, platform_confidence));
+    if load_store.yes + load_store.no > 0 {
+        evidence.push(format!(
+            "{} loads or stores rely on I moving, {} move it by hand",
+            load_store.yes, load_store.no
+        ));
+    }
+
+    QuirkGuess {
+        platform,
+        quirks: Quirks {
+            shift_vy,
+            load_store_increment,
+            ..defaults
+        },
+        confidence: (platform_confidence + shift_confidence + load_store_confidence) / 3.0,
+        evidence,
+    }
+}
+
+// The platform `opcode` first appeared on, if plain CHIP-8 didn't have it
+pub(crate) fn platform_only(opcode: u16) -> Option<Platform> {
+    match opcode {
+        // Long I load (F000 NNNN), audio pattern, plane select
+        0xF000 | 0xF002 => Some(Platform::XoChip),
+        _ if opcode & 0xF0FF == 0xF001 => Some(Platform::XoChip),
+        // Save and load register ranges
+        _ if opcode & 0xF00F == 0x5002 || opcode & 0xF00F == 0x5003 => Some(Platform::XoChip),
+        // Scroll right, scroll left, exit, low and high resolution
+        0x00FB..=0x00FF => Some(Platform::SuperChip),
+        // Scroll down
+        _ if opcode & 0xFFF0 == 0x00C0 && opcode != 0x00C0 => Some(Platform::SuperChip),
+        // Big font, and saving registers to flags
+        _ if matches!(opcode & 0xF0FF, 0xF030 | 0xF075 | 0xF085) => Some(Platform::SuperChip),
+        _ => None,
+    }
+}
+
+fn is_shift(opcode: u16) -> bool {
+    matches!(opcode & 0xF00F, 0x8006 | 0x800E)
+}
+
+// Whether the FX55 or FX65 at `index` is followed by code that needs I to
+// have moved past it (`true`), that moves I itself (`false`), or neither
+fn i_after_load_store(opcodes: &[u16], index: usize) -> Option<bool> {
+    // Sets I, or moves it
+    let sets_i = |opcode: u16| opcode & 0xF000 == 0xA000 || opcode & 0xF0FF == 0xF01E;
+
+    for &next in opcodes.iter().skip(index + 1).take(LOOKAHEAD) {
+        match next {
+            _ if next & 0xF000 == 0xA000 => return None,
+            _ if next & 0xF0FF == 0xF01E => return Some(false),
+            _ if matches!(next & 0xF0FF, 0xF055 | 0xF065) => return Some(true),
+            // A loop back over the load or store, which only reads or
+            // writes somewhere new each time round if I moves
+            _ if next & 0xF000 == 0x1000 => {
+                let target = (next & 0x0FFF) as usize;
+                if target < PROGRAM_START || !target.is_multiple_of(2) {
+                    return None;
+                }
+                let start = (target - PROGRAM_START) / 2;
+                if start > index {
+                    return None;
+                }
+
+                return match opcodes[start..index].iter().any(|op| sets_i(*op)) {
+                    true => None,
+                    false => Some(true),
+                };
+            }
+            // Anything else that leaves straight line code
+            _ if matches!(next & 0xF000, 0x2000 | 0xB000) || next == 0x00EE => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::{Chip8, FrameTimer, SharedKeypad};
+    use crate::headless::HeadlessScreen;
+
+    #[test]
+    fn test_schip_rom() {
+        // High resolution, clear, draw a 16x16 sprite, exit
+        let rom = [0x00, 0xFF, 0x00, 0xE0, 0xD0, 0x10, 0x00, 0xFD];
+        let guess = guess_quirks(&rom);
+
+        assert_eq!(guess.platform, Platform::SuperChip);
+        assert_eq!(guess.quirks, Quirks::default());
+        assert_eq!(guess.confidence, 1.0 - 0.5f64.powi(3));
+        assert_eq!(guess.evidence.len(), 2);
+        assert_eq!(guess.to_string(), "SCHIP (no quirks), 88% sure");
+    }
+
+    #[test]
+    fn test_cosmac_rom() {
+        // V1 = V2 >> 1, then store V0-V3 and load them back in a loop
+        // that never sets I
+        let rom = [
+            0xA3, 0x00, // LD I, 0x300
+            0x81, 0x26, // SHR V1, V2
+            0xF3, 0x55, // LD [I], V3
+            0x70, 0x01, // ADD V0, 1
+            0x12, 0x04, // JP 0x204
+        ];
+        let guess = guess_quirks(&rom);
+
+        assert_eq!(guess.platform, Platform::Chip8);
+        assert!(guess.quirks.shift_vy);
+        assert!(guess.quirks.load_store_increment);
+        assert!(!guess.quirks.display_wait);
+    }
+
+    #[test]
+    fn test_i_moved_by_hand() {
+        // Shift written the SCHIP way, and I moved on after each store
+        let rom = [
+            0x81, 0x06, // SHR V1
+            0xF3, 0x55, // LD [I], V3
+            0xF4, 0x1E, // ADD I, V4
+        ];
+        let guess = guess_quirks(&rom);
+
+        assert_eq!(guess.quirks, Quirks::default());
+        assert_eq!(guess.confidence, (0.5 + 1.0 + 1.0) / 3.0);
+    }
+
+    #[test]
+    fn test_applied_on_load() {
+        let mut chip8 = Chip8::new(
+            Box::new(HeadlessScreen::new()),
+            Box::new(SharedKeypad::new()),
+            Box::new(FrameTimer::new()),
+        );
+
+        chip8.guess_quirks_on_load();
+        chip8.init_memory(&[0x81, 0x26]).unwrap();
+        assert!(chip8.quirks().shift_vy);
+
+        // Quirks that were chosen aren't guessed over
+        chip8.set_quirks(Quirks::default());
+        chip8.init_memory(&[0x81, 0x26]).unwrap();
+        assert_eq!(chip8.quirks(), Quirks::default());
+    }
+}
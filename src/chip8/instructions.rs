@@ -118,6 +118,7 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    #[allow(clippy::result_unit_err)]
     pub fn from_bytes(raw: [u8; 2]) -> Result<Instruction, ()> {
         // Instructions have arguments in a few standard places
         // 0nnn - 12 bit address
@@ -136,16 +137,19 @@ impl Instruction {
         let kk = raw[1];
 
         return match first_nibble(raw[0]) {
-            0x0 => match raw[1] {
-                0xE0 => Ok(Instruction::ClearScreen()),
-                0xEE => Ok(Instruction::SubroutineReturn()),
+            0x0 => match raw {
+                [0x00, 0xE0] => Ok(Instruction::ClearScreen()),
+                [0x00, 0xEE] => Ok(Instruction::SubroutineReturn()),
                 _ => Err(()),
             },
             0x1 => Ok(Instruction::Jump(nnn)),
             0x2 => Ok(Instruction::SubroutineCall(nnn)),
             0x3 => Ok(Instruction::SkipIfEqualImm(x, kk)),
             0x4 => Ok(Instruction::SkipIfNotEqualImm(x, kk)),
-            0x5 => Ok(Instruction::SkipIfEqualReg(x, y)),
+            0x5 => match n {
+                0x0 => Ok(Instruction::SkipIfEqualReg(x, y)),
+                _ => Err(()),
+            },
             0x6 => Ok(Instruction::LoadRegImm(x, kk)),
             0x7 => Ok(Instruction::AddImm(x, kk)),
             0x8 => match last_nibble(raw[1]) {
@@ -160,7 +164,10 @@ impl Instruction {
                 0xE => Ok(Instruction::ShiftLeft(x, y)),
                 _ => Err(()),
             },
-            0x9 => Ok(Instruction::SkipIfNotEqualReg(x, y)),
+            0x9 => match n {
+                0x0 => Ok(Instruction::SkipIfNotEqualReg(x, y)),
+                _ => Err(()),
+            },
             0xA => Ok(Instruction::LoadAddress(nnn)),
             0xB => Ok(Instruction::JumpV0(nnn)),
             0xC => Ok(Instruction::Random(x, kk)),
@@ -185,6 +192,100 @@ impl Instruction {
             _ => Err(()),
         };
     }
+
+    // The instruction as assembly, "???" if it doesn't decode
+    pub fn disassemble(raw: [u8; 2]) -> String {
+        match Instruction::from_bytes(raw) {
+            Ok(inst) => inst.to_string(),
+            Err(_) => String::from("???"),
+        }
+    }
+
+    // Opposite of `from_bytes`
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let nnn = |op: u16, addr: u16| (op << 12 | (addr & 0x0FFF)).to_be_bytes();
+        let xkk = |op: u8, x: u8, kk: u8| [op << 4 | (x & 0x0F), kk];
+        let xyn =
+            |op: u8, x: u8, y: u8, n: u8| [op << 4 | (x & 0x0F), (y & 0x0F) << 4 | (n & 0x0F)];
+
+        match *self {
+            Instruction::ClearScreen() => [0x00, 0xE0],
+            Instruction::SubroutineReturn() => [0x00, 0xEE],
+            Instruction::Jump(addr) => nnn(0x1, addr),
+            Instruction::SubroutineCall(addr) => nnn(0x2, addr),
+            Instruction::SkipIfEqualImm(x, kk) => xkk(0x3, x, kk),
+            Instruction::SkipIfNotEqualImm(x, kk) => xkk(0x4, x, kk),
+            Instruction::SkipIfEqualReg(x, y) => xyn(0x5, x, y, 0x0),
+            Instruction::LoadRegImm(x, kk) => xkk(0x6, x, kk),
+            Instruction::AddImm(x, kk) => xkk(0x7, x, kk),
+            Instruction::LoadRegReg(x, y) => xyn(0x8, x, y, 0x0),
+            Instruction::OrReg(x, y) => xyn(0x8, x, y, 0x1),
+            Instruction::AndReg(x, y) => xyn(0x8, x, y, 0x2),
+            Instruction::XorReg(x, y) => xyn(0x8, x, y, 0x3),
+            Instruction::AddReg(x, y) => xyn(0x8, x, y, 0x4),
+            Instruction::SubtractReg(x, y) => xyn(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            Instruction::SubtractRegSwapped(x, y) => xyn(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xyn(0x8, x, y, 0xE),
+            Instruction::SkipIfNotEqualReg(x, y) => xyn(0x9, x, y, 0x0),
+            Instruction::LoadAddress(addr) => nnn(0xA, addr),
+            Instruction::JumpV0(addr) => nnn(0xB, addr),
+            Instruction::Random(x, kk) => xkk(0xC, x, kk),
+            Instruction::Draw(x, y, n) => xyn(0xD, x, y, n),
+            Instruction::SkipIfPressed(x) => xkk(0xE, x, 0x9E),
+            Instruction::SkipIfNotPressed(x) => xkk(0xE, x, 0xA1),
+            Instruction::LoadRegDelay(x) => xkk(0xF, x, 0x07),
+            Instruction::LoadKey(x) => xkk(0xF, x, 0x0A),
+            Instruction::LoadDelayReg(x) => xkk(0xF, x, 0x15),
+            Instruction::LoadSoundReg(x) => xkk(0xF, x, 0x18),
+            Instruction::AddAddress(x) => xkk(0xF, x, 0x1E),
+            Instruction::LoadAddressDigit(x) => xkk(0xF, x, 0x29),
+            Instruction::LoadMemoryBcd(x) => xkk(0xF, x, 0x33),
+            Instruction::LoadMemoryRegisters(x) => xkk(0xF, x, 0x55),
+            Instruction::LoadRegistersMemory(x) => xkk(0xF, x, 0x65),
+        }
+    }
+
+    // The opcode this was decoded from with its arguments as letters,
+    // e.g. "8XY4" or "DXYN"
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::ClearScreen() => "00E0",
+            Instruction::SubroutineReturn() => "00EE",
+            Instruction::Jump(_) => "1NNN",
+            Instruction::SubroutineCall(_) => "2NNN",
+            Instruction::SkipIfEqualImm(..) => "3XKK",
+            Instruction::SkipIfNotEqualImm(..) => "4XKK",
+            Instruction::SkipIfEqualReg(..) => "5XY0",
+            Instruction::LoadRegImm(..) => "6XKK",
+            Instruction::AddImm(..) => "7XKK",
+            Instruction::LoadRegReg(..) => "8XY0",
+            Instruction::OrReg(..) => "8XY1",
+            Instruction::AndReg(..) => "8XY2",
+            Instruction::XorReg(..) => "8XY3",
+            Instruction::AddReg(..) => "8XY4",
+            Instruction::SubtractReg(..) => "8XY5",
+            Instruction::ShiftRight(..) => "8XY6",
+            Instruction::SubtractRegSwapped(..) => "8XY7",
+            Instruction::ShiftLeft(..) => "8XYE",
+            Instruction::SkipIfNotEqualReg(..) => "9XY0",
+            Instruction::LoadAddress(_) => "ANNN",
+            Instruction::JumpV0(_) => "BNNN",
+            Instruction::Random(..) => "CXKK",
+            Instruction::Draw(..) => "DXYN",
+            Instruction::SkipIfPressed(_) => "EX9E",
+            Instruction::SkipIfNotPressed(_) => "EXA1",
+            Instruction::LoadRegDelay(_) => "FX07",
+            Instruction::LoadKey(_) => "FX0A",
+            Instruction::LoadDelayReg(_) => "FX15",
+            Instruction::LoadSoundReg(_) => "FX18",
+            Instruction::AddAddress(_) => "FX1E",
+            Instruction::LoadAddressDigit(_) => "FX29",
+            Instruction::LoadMemoryBcd(_) => "FX33",
+            Instruction::LoadMemoryRegisters(_) => "FX55",
+            Instruction::LoadRegistersMemory(_) => "FX65",
+        }
+    }
 }
 
 impl fmt::Display for Instruction {
@@ -246,6 +347,36 @@ mod test {
         assert_eq!(last_nibble(0x5F), 0xF);
     }
 
+    #[test]
+    fn test_pattern() {
+        assert_eq!(
+            Instruction::from_bytes([0x00, 0xE0]).unwrap().pattern(),
+            "00E0"
+        );
+        assert_eq!(
+            Instruction::from_bytes([0x8A, 0xB4]).unwrap().pattern(),
+            "8XY4"
+        );
+        assert_eq!(
+            Instruction::from_bytes([0xF3, 0x65]).unwrap().pattern(),
+            "FX65"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Every opcode either fails to decode or encodes back to itself
+        for raw in 0..=u16::MAX {
+            let bytes = raw.to_be_bytes();
+            if let Ok(instruction) = Instruction::from_bytes(bytes) {
+                assert_eq!(instruction.to_bytes(), bytes, "{}", instruction);
+            }
+        }
+
+        assert!(Instruction::from_bytes([0x51, 0x21]).is_err());
+        assert!(Instruction::from_bytes([0x91, 0x2F]).is_err());
+    }
+
     #[test]
     fn test_address() {
         assert_eq!(address([0xAB, 0x56]), 0xB56);
@@ -0,0 +1,167 @@
+// Key presses and releases from the host, each with the time it happened.
+// A frame's worth of them is turned into the key held during each
+// instruction of the frame, so a tap between two host callbacks still lands
+// on the instruction it happened at. Every press is held for at least a
+// frame, so games that only check keys once a frame still see short taps.
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    // Milliseconds, on the same clock as the frame times given to `frame`
+    pub time: f64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug)]
+struct HeldKey {
+    key: u8,
+    // The same key can be held down by several inputs at once
+    presses: u32,
+    // Instruction the key went down at
+    since_step: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct KeyQueue {
+    events: VecDeque<KeyEvent>,
+
+    // Keys held down, in the order they were pressed
+    held: Vec<HeldKey>,
+
+    // Instructions run since the queue was created
+    step: u64,
+}
+
+impl KeyQueue {
+    pub fn new() -> KeyQueue {
+        KeyQueue::default()
+    }
+
+    // Events have to be pushed in time order
+    pub fn push(&mut self, event: KeyEvent) {
+        self.events.push_back(event);
+    }
+
+    // Key held down during each of the `steps` instructions of a frame that
+    // runs from `since` to `until`. Events are spread over the frame by their
+    // time, anything after the frame is left for the next one.
+    pub fn frame(&mut self, since: f64, until: f64, steps: usize) -> Vec<Option<u8>> {
+        let mut keys = Vec::with_capacity(steps);
+
+        for step in 0..steps {
+            let step_time = since + (until - since) * step as f64 / steps as f64;
+            while let Some(event) = self.events.front() {
+                if event.time > step_time {
+                    break;
+                }
+                let event = *event;
+                self.events.pop_front();
+                self.apply(event);
+            }
+
+            // Let go of released keys once they've been seen for a frame
+            let step = self.step;
+            self.held
+                .retain(|held| held.presses > 0 || step < held.since_step + steps as u64);
+
+            // The first key pressed wins until it's lifted
+            keys.push(self.held.first().map(|held| held.key));
+            self.step += 1;
+        }
+
+        keys
+    }
+
+    fn apply(&mut self, event: KeyEvent) {
+        let held = self.held.iter_mut().find(|held| held.key == event.key);
+
+        match (held, event.pressed) {
+            (Some(held), true) => {
+                // A key pressed again before its last press was let go
+                // starts over, so the new press is held for a frame too
+                if held.presses == 0 {
+                    held.since_step = self.step;
+                }
+                held.presses += 1;
+            }
+            (Some(held), false) => held.presses = held.presses.saturating_sub(1),
+            (None, true) => self.held.push(HeldKey {
+                key: event.key,
+                presses: 1,
+                since_step: self.step,
+            }),
+            // Released without being pressed, e.g. held down before loading
+            (None, false) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(time: f64, key: u8, pressed: bool) -> KeyEvent {
+        KeyEvent { time, key, pressed }
+    }
+
+    #[test]
+    fn test_events_land_on_their_step() {
+        let mut queue = KeyQueue::new();
+        queue.push(event(5.0, 0x5, true));
+        // After the first frame ends
+        queue.push(event(13.0, 0x6, true));
+        queue.push(event(15.0, 0x5, false));
+
+        // Steps start at 0, 3, 6 and 9ms
+        let keys = queue.frame(0.0, 12.0, 4);
+        assert_eq!(keys, [None, None, Some(0x5), Some(0x5)]);
+
+        // 5 was held for a whole frame by the third step, so 6 takes over
+        let keys = queue.frame(12.0, 24.0, 4);
+        assert_eq!(keys, [Some(0x5), Some(0x5), Some(0x6), Some(0x6)]);
+    }
+
+    #[test]
+    fn test_short_taps_last_a_frame() {
+        let mut queue = KeyQueue::new();
+        queue.push(event(1.0, 0xA, true));
+        queue.push(event(1.5, 0xA, false));
+
+        let mut keys = queue.frame(0.0, 12.0, 4);
+        keys.extend(queue.frame(12.0, 24.0, 4));
+        assert_eq!(
+            keys,
+            [
+                None,
+                Some(0xA),
+                Some(0xA),
+                Some(0xA),
+                Some(0xA),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_first_key_wins() {
+        let mut queue = KeyQueue::new();
+        queue.push(event(0.0, 0x1, true));
+        queue.push(event(0.0, 0x2, true));
+        // Two inputs holding the same key
+        queue.push(event(0.0, 0x1, true));
+        queue.push(event(20.0, 0x1, false));
+
+        assert_eq!(queue.frame(0.0, 12.0, 2), [Some(0x1), Some(0x1)]);
+        assert_eq!(queue.frame(12.0, 24.0, 2), [Some(0x1), Some(0x1)]);
+
+        queue.push(event(30.0, 0x1, false));
+        assert_eq!(queue.frame(24.0, 36.0, 2), [Some(0x1), Some(0x2)]);
+
+        // Releasing a key that was never pressed does nothing
+        queue.push(event(40.0, 0x7, false));
+        assert_eq!(queue.frame(36.0, 48.0, 2), [Some(0x2), Some(0x2)]);
+    }
+}
@@ -2,16 +2,36 @@ mod chip8;
 pub use self::chip8::*;
 
 mod instructions;
-use self::instructions::Instruction;
+pub use self::instructions::Instruction;
 
 pub mod traits;
 
+mod quirks;
+pub use self::quirks::Quirks;
+
+mod detect;
+pub(crate) use self::detect::platform_only;
+pub use self::detect::{guess_quirks, Platform, QuirkGuess};
+
+mod rng;
+
+mod peripherals;
+pub use self::peripherals::{FrameTimer, SharedKeypad};
+
+mod key_queue;
+pub use self::key_queue::{KeyEvent, KeyQueue};
+
 use std::fmt;
 
+#[derive(Debug)]
 pub enum Chip8Error {
     RomTooBig(usize),
     InvalidInstruction(u16, u16),
     InstructionNotImplemented(Instruction),
+    // (address, pc) Memory outside of the 4K address space was read or written
+    MemoryOutOfBounds(u16, u16),
+    // (pc) A call with every level of the stack already in use
+    StackOverflow(u16),
 }
 
 impl fmt::Display for Chip8Error {
@@ -24,6 +44,10 @@ impl fmt::Display for Chip8Error {
             Chip8Error::InstructionNotImplemented(inst) => {
                 write!(f, "InstructionNotImplemented {}", inst)
             }
+            Chip8Error::MemoryOutOfBounds(addr, pc) => {
+                write!(f, "MemoryOutOfBounds {:#04X} at {:#04X}", addr, pc)
+            }
+            Chip8Error::StackOverflow(pc) => write!(f, "StackOverflow at {:#04X}", pc),
         }
     }
 }
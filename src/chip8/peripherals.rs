@@ -0,0 +1,52 @@
+// Timer and keypad whose state is set by the host between frames. Time only
+// moves forward when the host says a frame has passed and the keypad only
+// changes when the host sets it, so a run depends on nothing but its inputs.
+use super::traits::{HexKeyboard, Timer};
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Counts 60Hz frames, clones share the same count
+#[derive(Clone, Default)]
+pub struct FrameTimer {
+    cycles: Rc<Cell<u8>>,
+}
+
+impl FrameTimer {
+    pub fn new() -> FrameTimer {
+        FrameTimer::default()
+    }
+
+    // Call once at the end of every frame
+    pub fn tick(&self) {
+        self.cycles.set(self.cycles.get().saturating_add(1));
+    }
+}
+
+impl Timer for FrameTimer {
+    fn cycles_passed(&self) -> u8 {
+        self.cycles.replace(0)
+    }
+}
+
+// The key held down this frame, clones share the same key
+#[derive(Clone, Default)]
+pub struct SharedKeypad {
+    key: Rc<Cell<Option<u8>>>,
+}
+
+impl SharedKeypad {
+    pub fn new() -> SharedKeypad {
+        SharedKeypad::default()
+    }
+
+    pub fn set(&self, key: Option<u8>) {
+        self.key.set(key);
+    }
+}
+
+impl HexKeyboard for SharedKeypad {
+    fn pressed_key(&self) -> Option<u8> {
+        self.key.get()
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Behaviours that differ between Chip-8 interpreters. Programs were
+// written against whichever interpreter their author had, so some of
+// them only work with a particular combination of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    // The original COSMAC VIP interpreter waited for the next vertical blank
+    // before drawing a sprite, limiting programs to one draw per frame.
+    pub display_wait: bool,
+
+    // The COSMAC VIP shifted Vy into Vx for 8XY6 and 8XYE. Later
+    // interpreters shift Vx in place and ignore Vy.
+    pub shift_vy: bool,
+
+    // The COSMAC VIP left I pointing past the last register FX55 and FX65
+    // stored or loaded. Later interpreters leave I alone.
+    pub load_store_increment: bool,
+}
+
+impl Quirks {
+    // Name and setting of every quirk, in the order they're written out
+    fn flags(&self) -> [(&'static str, bool); 3] {
+        [
+            ("display_wait", self.display_wait),
+            ("shift_vy", self.shift_vy),
+            ("load_store_increment", self.load_store_increment),
+        ]
+    }
+
+    fn set_flag(&mut self, name: &str) -> Result<(), ()> {
+        match name {
+            "display_wait" => self.display_wait = true,
+            "shift_vy" => self.shift_vy = true,
+            "load_store_increment" => self.load_store_increment = true,
+            _ => return Err(()),
+        }
+
+        Ok(())
+    }
+
+    // Every quirk, as written in lists
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        IntoIterator::into_iter(Quirks::default().flags()).map(|(name, _)| name)
+    }
+}
+
+// Written as a comma separated list of the quirks that are turned on,
+// e.g. "display_wait", empty when running with none of them
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let enabled: Vec<&str> = self
+            .flags()
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+
+        write!(f, "{}", enabled.join(","))
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut quirks = Quirks::default();
+
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            quirks.set_flag(name)?;
+        }
+
+        Ok(quirks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quirks_round_trip() {
+        let none = Quirks::default();
+        assert_eq!(none.to_string(), "");
+        assert_eq!("".parse(), Ok(none));
+
+        let wait = Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        };
+        assert_eq!(wait.to_string(), "display_wait");
+        assert_eq!("display_wait".parse(), Ok(wait));
+
+        let vip = Quirks {
+            display_wait: true,
+            shift_vy: true,
+            load_store_increment: true,
+        };
+        assert_eq!(
+            vip.to_string(),
+            "display_wait,shift_vy,load_store_increment"
+        );
+        assert_eq!(
+            "load_store_increment, shift_vy,display_wait".parse(),
+            Ok(vip)
+        );
+
+        assert_eq!("not_a_quirk".parse::<Quirks>(), Err(()));
+    }
+}
@@ -0,0 +1,86 @@
+// Random numbers for the CXKK instruction. The generator is seeded so a run
+// can be repeated exactly, e.g. when playing back a recorded movie.
+// xorshift64* https://en.wikipedia.org/wiki/Xorshift#xorshift*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            seed,
+            state: scramble(seed),
+        }
+    }
+
+    // The seed this generator was started from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Where the generator is in its sequence, for saving it
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    // A generator saved part way through its sequence, `None` if the state
+    // could never have come from `new`
+    pub fn from_parts(seed: u64, state: u64) -> Option<Rng> {
+        match state {
+            0 => None,
+            _ => Some(Rng { seed, state }),
+        }
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        // The high bits are the most random
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+// xorshift can't start from zero and similar seeds start off with similar
+// output, so mix the seed first (splitmix64 finaliser)
+fn scramble(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    if z == 0 {
+        1
+    } else {
+        z
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let mut a = Rng::new(1234);
+        let mut b = Rng::new(1234);
+        let mut c = Rng::new(1235);
+
+        let a: Vec<u8> = (0..32).map(|_| a.next_u8()).collect();
+        let b: Vec<u8> = (0..32).map(|_| b.next_u8()).collect();
+        let c: Vec<u8> = (0..32).map(|_| c.next_u8()).collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.seed(), 0);
+        assert!((0..32).any(|_| rng.next_u8() != 0));
+    }
+}
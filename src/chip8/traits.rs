@@ -1,9 +1,16 @@
+use crate::screen::RawGrid;
+
 pub trait Drawable {
     fn write_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool;
 
-    fn flush(&self);
+    fn grid(&self) -> &RawGrid;
+
+    fn flush(&mut self);
 
     fn clear(&mut self);
+
+    // Replace the whole framebuffer, e.g. when loading a saved state
+    fn set_grid(&mut self, grid: &RawGrid);
 }
 
 pub trait HexKeyboard {
@@ -0,0 +1,203 @@
+// Runs a ROM on two machines with different quirks, side by side, to find
+// out which quirk it needs. Both get the same keys on the same steps and
+// the same random numbers, so they only drift apart because of the quirks.
+// After every instruction their states are compared and the first one that
+// left them different is kept.
+use crate::chip8::traits::Drawable;
+use crate::chip8::{Chip8, Chip8Error, FrameTimer, Instruction, Quirks, SharedKeypad};
+
+use std::fmt;
+
+// The instruction that made the two machines differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: u64,
+    pub step: usize,
+    // Where the left machine was and what it ran, both were the same until
+    // this instruction
+    pub address: u16,
+    pub opcode: u16,
+    // See `Chip8::differences`, or the error one side stopped with
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Frame {}, step {}: {:#06X} {:04X} {} changed {}",
+            self.frame,
+            self.step,
+            self.address,
+            self.opcode,
+            Instruction::disassemble(self.opcode.to_be_bytes()),
+            self.differences.join(", ")
+        )
+    }
+}
+
+pub struct Comparison {
+    left: Chip8,
+    right: Chip8,
+
+    keypad: SharedKeypad,
+    // Each machine drains its own timer
+    timers: [FrameTimer; 2],
+
+    frame: u64,
+    divergence: Option<Divergence>,
+}
+
+impl Comparison {
+    pub fn new(
+        screens: [Box<dyn Drawable>; 2],
+        rom: &[u8],
+        quirks: [Quirks; 2],
+        seed: u64,
+    ) -> Result<Comparison, Chip8Error> {
+        let keypad = SharedKeypad::new();
+        let timers = [FrameTimer::new(), FrameTimer::new()];
+
+        let [left_screen, right_screen] = screens;
+        let machine = |screen, quirks, timer: &FrameTimer| {
+            let mut chip8 = Chip8::new(screen, Box::new(keypad.clone()), Box::new(timer.clone()));
+            chip8.set_quirks(quirks);
+            chip8.set_seed(seed);
+            chip8.init_memory(rom).map(|_| chip8)
+        };
+        let left = machine(left_screen, quirks[0], &timers[0])?;
+        let right = machine(right_screen, quirks[1], &timers[1])?;
+
+        Ok(Comparison {
+            left,
+            right,
+            keypad,
+            timers,
+            frame: 0,
+            divergence: None,
+        })
+    }
+
+    pub fn left(&self) -> &Chip8 {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Chip8 {
+        &self.right
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // `None` while the machines are still in the same state
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    // Run both machines for one frame, a step each per key in `keys`. An
+    // error on either side stops the frame.
+    pub fn run_frame(&mut self, keys: &[Option<u8>]) -> Result<(), Chip8Error> {
+        let result = keys
+            .iter()
+            .enumerate()
+            .try_for_each(|(step, key)| self.step(step, *key));
+
+        self.left.vblank();
+        self.right.vblank();
+        for timer in &self.timers {
+            timer.tick();
+        }
+        self.frame += 1;
+
+        result
+    }
+
+    fn step(&mut self, step: usize, key: Option<u8>) -> Result<(), Chip8Error> {
+        self.keypad.set(key);
+
+        let address = self.left.program_counter();
+        let opcode = self
+            .left
+            .memory()
+            .get(address as usize..address as usize + 2)
+            .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+
+        let left = self.left.step_execution();
+        let right = self.right.step_execution();
+
+        if self.divergence.is_none() {
+            let differences = match (&left, &right) {
+                (Err(e), Ok(())) => vec![format!("left stopped with {}", e)],
+                (Ok(()), Err(e)) => vec![format!("right stopped with {}", e)],
+                _ => self.left.differences(&self.right),
+            };
+
+            if !differences.is_empty() {
+                self.divergence = Some(Divergence {
+                    frame: self.frame,
+                    step,
+                    address,
+                    opcode,
+                    differences,
+                });
+            }
+        }
+
+        left.and(right)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::STEPS_PER_FRAME;
+    use crate::headless::HeadlessScreen;
+
+    fn screens() -> [Box<dyn Drawable>; 2] {
+        [
+            Box::new(HeadlessScreen::new()),
+            Box::new(HeadlessScreen::new()),
+        ]
+    }
+
+    #[test]
+    fn test_display_wait_divergence() {
+        // Random number, draw, count and loop. Display wait holds the right
+        // machine at the draw until the next frame.
+        let rom = [0xC0, 0xFF, 0xD1, 0x15, 0x72, 0x01, 0x12, 0x04];
+        let quirks = [
+            Quirks::default(),
+            Quirks {
+                display_wait: true,
+                ..Quirks::default()
+            },
+        ];
+
+        let mut comparison = Comparison::new(screens(), &rom, quirks, 7).unwrap();
+        comparison.run_frame(&[None; STEPS_PER_FRAME]).unwrap();
+
+        let divergence = comparison.divergence().unwrap();
+        assert_eq!(divergence.frame, 0);
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.address, 0x202);
+        assert_eq!(divergence.opcode, 0xD115);
+        assert_eq!(divergence.differences, ["waiting for vblank"]);
+        assert!(divergence
+            .to_string()
+            .starts_with("Frame 0, step 1: 0x0202 D115"));
+    }
+
+    #[test]
+    fn test_same_quirks_never_diverge() {
+        let rom = [0xC0, 0xFF, 0xD1, 0x15, 0x72, 0x01, 0x12, 0x00];
+        let quirks = [Quirks::default(); 2];
+
+        let mut comparison = Comparison::new(screens(), &rom, quirks, 7).unwrap();
+        for _ in 0..60 {
+            comparison.run_frame(&[Some(5); STEPS_PER_FRAME]).unwrap();
+        }
+        assert_eq!(comparison.divergence(), None);
+        assert_eq!(comparison.left().screen(), comparison.right().screen());
+    }
+}
@@ -0,0 +1,214 @@
+// What the machine looked like when an instruction failed, both for showing
+// on the page and for attaching to bug reports. A report is saved as text:
+//
+//   chip8-crash 1
+//   error InvalidInstruction 0x00 at 0x204
+//   at 0x0204 0000 ???
+//   registers 00 01 00 ...
+//   i 0x0300
+//   timers 0 0
+//   stack 0x0204
+//   seed 1234
+//   trace 0x0200 6001 LD V0, 0x01
+//   trace 0x0202 2204 CALL 0x204
+//   chip8-state 1
+//   ...
+//
+// "trace" lines are the last instructions run, oldest first. Everything from
+// "chip8-state" on is a `SaveState`, so the crash can be loaded and stepped
+// through by importing that part as a save state.
+
+use crate::chip8::{Chip8, Chip8Error, Instruction};
+use crate::save_state::SaveState;
+
+use std::fmt;
+
+const HEADER: &str = "chip8-crash 1";
+
+// Instructions kept for the trace
+pub const TRACE_LENGTH: usize = 64;
+
+pub struct CrashReport {
+    pub error: String,
+    // Address of the instruction that failed and its opcode, if the address
+    // is in memory
+    pub address: u16,
+    pub opcode: Option<u16>,
+
+    registers: Vec<u8>,
+    i_reg: u16,
+    delay: u8,
+    sound: u8,
+    stack: Vec<u16>,
+    seed: u64,
+    trace: Vec<(u16, u16)>,
+
+    pub state: SaveState,
+}
+
+impl CrashReport {
+    // `state` should have been saved from `chip8` straight after `error`
+    pub fn new(error: &Chip8Error, chip8: &Chip8, state: SaveState) -> CrashReport {
+        let address = match *error {
+            Chip8Error::InvalidInstruction(_, pc)
+            | Chip8Error::MemoryOutOfBounds(_, pc)
+            | Chip8Error::StackOverflow(pc) => pc,
+            // Anything else failed after the instruction was fetched
+            _ => chip8.program_counter().wrapping_sub(2),
+        };
+
+        let opcode = chip8
+            .memory()
+            .get(address as usize..address as usize + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+
+        CrashReport {
+            error: error.to_string(),
+            address,
+            opcode,
+            registers: chip8.registers().to_vec(),
+            i_reg: chip8.i_register(),
+            delay: chip8.delay_timer(),
+            sound: chip8.sound_timer(),
+            stack: chip8.stack().to_vec(),
+            seed: chip8.seed(),
+            trace: chip8.trace().copied().collect(),
+            state,
+        }
+    }
+
+    // Where to carry on from to skip the instruction that failed
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(2)
+    }
+
+    // The error and the machine state, for the page
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{}\n\n", self.error);
+
+        summary += &match self.opcode {
+            Some(opcode) => format!(
+                "{:#06X}  {:04X}  {}\n",
+                self.address,
+                opcode,
+                disassemble(opcode)
+            ),
+            None => format!("{:#06X}  outside of memory\n", self.address),
+        };
+
+        for (i, val) in self.registers.iter().enumerate() {
+            summary += &format!(
+                "V{:X}: {:#04X}{}",
+                i,
+                val,
+                if i % 4 == 3 { "\n" } else { "  " }
+            );
+        }
+        summary += &format!(
+            "I:  {:#06X}  DT: {:#04X}  ST: {:#04X}\n",
+            self.i_reg, self.delay, self.sound
+        );
+
+        summary += "Stack:";
+        if self.stack.is_empty() {
+            summary += " (empty)";
+        }
+        // Top of the stack first
+        for addr in self.stack.iter().rev() {
+            summary += &format!(" {:#06X}", addr);
+        }
+        summary.push('\n');
+
+        summary
+    }
+}
+
+fn disassemble(opcode: u16) -> String {
+    Instruction::disassemble(opcode.to_be_bytes())
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "error {}", self.error)?;
+        match self.opcode {
+            Some(opcode) => writeln!(
+                f,
+                "at {:#06x} {:04x} {}",
+                self.address,
+                opcode,
+                disassemble(opcode)
+            )?,
+            None => writeln!(f, "at {:#06x}", self.address)?,
+        }
+
+        let registers: Vec<String> = self
+            .registers
+            .iter()
+            .map(|v| format!("{:02x}", v))
+            .collect();
+        writeln!(f, "registers {}", registers.join(" "))?;
+        writeln!(f, "i {:#06x}", self.i_reg)?;
+        writeln!(f, "timers {} {}", self.delay, self.sound)?;
+
+        let stack: Vec<String> = self.stack.iter().map(|a| format!("{:#06x}", a)).collect();
+        writeln!(f, "stack {}", stack.join(" "))?;
+        writeln!(f, "seed {}", self.seed)?;
+
+        for (addr, opcode) in &self.trace {
+            writeln!(
+                f,
+                "trace {:#06x} {:04x} {}",
+                addr,
+                opcode,
+                disassemble(*opcode)
+            )?;
+        }
+
+        match self.state.to_text() {
+            Ok(state) => write!(f, "{}", state),
+            // The rest of the report is still worth having
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::{Quirks, STEPS_PER_FRAME};
+    use crate::headless::Headless;
+
+    // Sets V0 then calls a subroutine that runs into an invalid instruction
+    const ROM: [u8; 6] = [0x60, 0x2A, 0x22, 0x04, 0x00, 0x00];
+
+    #[test]
+    fn test_crash_report() {
+        let mut headless = Headless::new(&ROM, Quirks::default()).unwrap();
+        headless.chip8_mut().set_trace_length(TRACE_LENGTH);
+        let error = headless.run_frame(STEPS_PER_FRAME).unwrap_err();
+
+        let chip8 = headless.chip8();
+        let state = SaveState::new(
+            &ROM,
+            headless.frame(),
+            0,
+            Quirks::default(),
+            chip8.snapshot(),
+        );
+        let report = CrashReport::new(&error, chip8, state);
+        assert_eq!(report.address, 0x204);
+        assert_eq!(report.opcode, Some(0x0000));
+        assert_eq!(report.next_address(), 0x206);
+        assert!(report.summary().contains("Stack: 0x0204"));
+
+        let text = report.to_string();
+        assert!(text.starts_with("chip8-crash 1\nerror InvalidInstruction"));
+        assert_eq!(text.lines().filter(|l| l.starts_with("trace")).count(), 3);
+
+        // The save state on the end can be loaded on its own
+        let state = &text[text.find("chip8-state").unwrap()..];
+        let state: SaveState = state.parse().unwrap();
+        assert!(state.matches_rom(&ROM));
+    }
+}
@@ -0,0 +1,316 @@
+use crate::chip8::{Chip8, Instruction};
+use crate::crash::CrashReport;
+use crate::emulator::Emulator;
+use crate::movie::MovieError;
+use crate::start::download_bytes;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, Element};
+
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::rc::Rc;
+
+// Number of instructions shown on either side of the program counter
+const DISASSEMBLY_CONTEXT: u16 = 8;
+
+// Bytes per line in the memory view
+const MEMORY_ROW: usize = 16;
+
+// While running, the disassembly and memory view are only redrawn every this
+// many renders, rebuilding 4K of hex each frame slows everything else down
+const RUNNING_REDRAW_INTERVAL: u32 = 15;
+
+// Debugger renders the internal state of the Chip-8 into a panel on the page
+// and lets the user pause and single step execution. Everything here is plain
+// HTML generated in Rust, the page only needs to provide the elements.
+pub struct Debugger {
+    registers: Element,
+    stack: Element,
+    disassembly: Element,
+    memory: Element,
+    pause_button: Element,
+    status: Element,
+
+    error_card: Element,
+    error_details: Element,
+    error_actions: Element,
+
+    // Shared with the emulator's animation loop
+    paused: Rc<Cell<bool>>,
+    // The error on show, kept for the error card's buttons
+    crash: RefCell<Option<CrashReport>>,
+
+    renders: Cell<u32>,
+    // What the big panels last showed, so they're only touched on a change
+    disassembly_html: RefCell<String>,
+    memory_html: RefCell<String>,
+}
+
+impl Debugger {
+    pub fn new(paused: Rc<Cell<bool>>) -> Debugger {
+        Debugger {
+            registers: get_element("debug-registers"),
+            stack: get_element("debug-stack"),
+            disassembly: get_element("debug-disassembly"),
+            memory: get_element("debug-memory"),
+            pause_button: get_element("debug-pause"),
+            status: get_element("debug-status"),
+
+            error_card: get_element("errorcard"),
+            error_details: get_element("error-details"),
+            error_actions: get_element("error-actions"),
+
+            paused,
+            crash: RefCell::new(None),
+
+            renders: Cell::new(0),
+            disassembly_html: RefCell::new(String::new()),
+            memory_html: RefCell::new(String::new()),
+        }
+    }
+
+    // Wire the pause and step buttons to the emulator. The closures live as long
+    // as the page does, so they are intentionally leaked with `forget`.
+    pub fn attach(debugger: &Rc<Debugger>, emulator: &Rc<RefCell<Emulator>>) {
+        let pause_debugger = debugger.clone();
+        let pause_emulator = emulator.clone();
+        let on_pause = Closure::wrap(Box::new(move || {
+            pause_debugger.set_paused(!pause_debugger.is_paused());
+            pause_debugger.render(pause_emulator.borrow().chip8());
+        }) as Box<dyn FnMut()>);
+        add_click_listener("debug-pause", &on_pause);
+        on_pause.forget();
+
+        let step_debugger = debugger.clone();
+        let step_emulator = emulator.clone();
+        let on_step = Closure::wrap(Box::new(move || {
+            if !step_debugger.is_paused() {
+                return;
+            }
+
+            let mut emulator = step_emulator.borrow_mut();
+            match emulator.step() {
+                Err(MovieError::Emulator(e)) => {
+                    step_debugger.show_error(emulator.crash_report(&e, js_sys::Date::now() as u64))
+                }
+                Err(e) => step_debugger.set_status(&e.to_string()),
+                Ok(()) => {}
+            }
+            let chip8 = emulator.chip8_mut();
+            chip8.present();
+            step_debugger.render(chip8);
+        }) as Box<dyn FnMut()>);
+        add_click_listener("debug-step", &on_step);
+        on_step.forget();
+
+        // Skip the instruction that failed and carry on
+        let continue_debugger = debugger.clone();
+        let continue_emulator = emulator.clone();
+        let on_continue = Closure::wrap(Box::new(move || {
+            let next = continue_debugger
+                .crash
+                .borrow()
+                .as_ref()
+                .map(CrashReport::next_address);
+            if let Some(next) = next {
+                if let Err(e) = continue_emulator.borrow_mut().skip_to(next) {
+                    // Keep the error on show so it can still be reset
+                    continue_debugger.set_status(&e.to_string());
+                    return;
+                }
+            }
+            continue_debugger.crash.replace(None);
+            continue_debugger.hide_error();
+            continue_debugger.set_paused(false);
+        }) as Box<dyn FnMut()>);
+        add_click_listener("error-continue", &on_continue);
+        on_continue.forget();
+
+        let reset_debugger = debugger.clone();
+        let reset_emulator = emulator.clone();
+        let on_reset = Closure::wrap(Box::new(move || {
+            if let Err(e) = reset_emulator.borrow_mut().restart() {
+                reset_debugger.set_status(&e.to_string());
+                return;
+            }
+            reset_debugger.crash.replace(None);
+            reset_debugger.hide_error();
+            reset_debugger.set_paused(false);
+        }) as Box<dyn FnMut()>);
+        add_click_listener("error-reset", &on_reset);
+        on_reset.forget();
+
+        let download_debugger = debugger.clone();
+        let on_download = Closure::wrap(Box::new(move || {
+            if let Some(crash) = download_debugger.crash.borrow().as_ref() {
+                let filename = format!("chip8-crash-{:016x}.txt", crash.state.rom_hash);
+                download_bytes(crash.to_string().as_bytes(), &filename, "text/plain");
+            }
+        }) as Box<dyn FnMut()>);
+        add_click_listener("error-download", &on_download);
+        on_download.forget();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    // The pause button is updated on the next render
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.set(paused);
+    }
+
+    // Short message next to the buttons, e.g. the speed after a hotkey
+    pub fn set_status(&self, status: &str) {
+        self.status.set_text_content(Some(status));
+    }
+
+    // Pause and show what went wrong, with buttons to skip the instruction,
+    // reset, or download the report
+    pub fn show_error(&self, crash: CrashReport) {
+        console::warn_1(&JsValue::from(&crash.error));
+        self.set_paused(true);
+
+        self.error_details.set_text_content(Some(&crash.summary()));
+        let _ = self.error_actions.class_list().remove_1("hidden");
+        let _ = self.error_card.class_list().remove_1("hidden");
+        self.crash.replace(Some(crash));
+    }
+
+    pub fn hide_error(&self) {
+        let _ = self.error_card.class_list().add_1("hidden");
+    }
+
+    pub fn render(&self, chip8: &Chip8) {
+        let paused = self.paused.get();
+        self.pause_button
+            .set_text_content(Some(if paused { "Resume" } else { "Pause" }));
+
+        self.registers.set_inner_html(&format_registers(chip8));
+        self.stack.set_inner_html(&format_stack(chip8));
+
+        let renders = self.renders.get().wrapping_add(1);
+        self.renders.set(renders);
+        if paused || renders.is_multiple_of(RUNNING_REDRAW_INTERVAL) {
+            set_if_changed(
+                &self.disassembly,
+                &self.disassembly_html,
+                format_disassembly(chip8),
+            );
+            set_if_changed(&self.memory, &self.memory_html, format_memory(chip8));
+        }
+    }
+}
+
+fn set_if_changed(element: &Element, shown: &RefCell<String>, html: String) {
+    if *shown.borrow() != html {
+        element.set_inner_html(&html);
+        shown.replace(html);
+    }
+}
+
+fn get_element(id: &str) -> Element {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id(id)
+        .unwrap_or_else(|| panic!("Could not find debugger element #{}", id))
+}
+
+fn add_click_listener(id: &str, callback: &Closure<dyn FnMut()>) {
+    get_element(id)
+        .add_event_listener_with_callback("click", callback.as_ref().unchecked_ref())
+        .expect("Could not add click listener");
+}
+
+fn format_registers(chip8: &Chip8) -> String {
+    let mut html = String::new();
+
+    for (i, val) in chip8.registers().iter().enumerate() {
+        writeln!(html, "V{:X}: {:#04X}", i, val).unwrap();
+    }
+    writeln!(html, "I:  {:#06X}", chip8.i_register()).unwrap();
+    writeln!(html, "DT: {:#04X}", chip8.delay_timer()).unwrap();
+    writeln!(html, "ST: {:#04X}", chip8.sound_timer()).unwrap();
+    writeln!(html, "PC: {:#06X}", chip8.program_counter()).unwrap();
+    if chip8.is_waiting_for_key() {
+        writeln!(html, "Waiting for key").unwrap();
+    }
+
+    html
+}
+
+fn format_stack(chip8: &Chip8) -> String {
+    let mut html = String::new();
+
+    // Top of the stack first
+    for (depth, addr) in chip8.stack().iter().enumerate().rev() {
+        writeln!(html, "{:X}: {:#06X}", depth, addr).unwrap();
+    }
+    if chip8.stack().is_empty() {
+        writeln!(html, "(empty)").unwrap();
+    }
+
+    html
+}
+
+fn format_disassembly(chip8: &Chip8) -> String {
+    let mut html = String::new();
+
+    let pc = chip8.program_counter();
+    let mem = chip8.memory();
+
+    // Stay aligned with the program counter, chip-8 code isn't always on even addresses
+    let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+    let end = pc.saturating_add(DISASSEMBLY_CONTEXT * 2);
+
+    for addr in (start..=end).step_by(2) {
+        let index = addr as usize;
+        if index + 1 >= mem.len() {
+            break;
+        }
+
+        let bytes = [mem[index], mem[index + 1]];
+        let text = Instruction::disassemble(bytes);
+
+        let line = format!("{:#06X}  {:04X}  {}", addr, u16::from_be_bytes(bytes), text);
+        if addr == pc {
+            writeln!(html, "<mark>{}</mark>", line).unwrap();
+        } else {
+            writeln!(html, "{}", line).unwrap();
+        }
+    }
+
+    html
+}
+
+fn format_memory(chip8: &Chip8) -> String {
+    let mut html = String::new();
+
+    let pc = chip8.program_counter() as usize;
+    let i = chip8.i_register() as usize;
+
+    for (row, bytes) in chip8.memory().chunks(MEMORY_ROW).enumerate() {
+        write!(html, "{:03X}:", row * MEMORY_ROW).unwrap();
+
+        for (col, byte) in bytes.iter().enumerate() {
+            let addr = row * MEMORY_ROW + col;
+
+            // PC covers both bytes of the current instruction
+            if addr == pc || addr == pc + 1 {
+                write!(html, " <mark class=\"tertiary\">{:02X}</mark>", byte).unwrap();
+            } else if addr == i {
+                write!(html, " <mark class=\"secondary\">{:02X}</mark>", byte).unwrap();
+            } else {
+                write!(html, " {:02X}", byte).unwrap();
+            }
+        }
+
+        html.push('\n');
+    }
+
+    html
+}
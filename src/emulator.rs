@@ -0,0 +1,328 @@
+use crate::chip8::traits::Drawable;
+use crate::chip8::{
+    Chip8, Chip8Error, FrameTimer, KeyQueue, Quirks, SharedKeypad, Snapshot, STEPS_PER_FRAME,
+};
+use crate::crash::{CrashReport, TRACE_LENGTH};
+use crate::hotkeys::Hotkey;
+use crate::keyboard::Keyboard;
+use crate::movie::{rom_hash, Movie, MovieError};
+use crate::save_state::{SaveState, SaveStateError};
+
+use rand::random;
+
+enum MovieMode {
+    Off,
+    Recording(Movie),
+    Playing(Movie),
+}
+
+impl MovieMode {
+    // The keys held during each instruction of the next frame, given the
+    // keys pressed on the keyboard. Recording keeps them, playing a movie
+    // back replaces them.
+    fn frame_keys(&mut self, frame: u64, pressed: Vec<Option<u8>>) -> Vec<Option<u8>> {
+        match self {
+            MovieMode::Playing(movie) => movie.frame_keys(frame, STEPS_PER_FRAME),
+            MovieMode::Recording(movie) => {
+                movie.record(&pressed);
+                pressed
+            }
+            MovieMode::Off => pressed,
+        }
+    }
+
+    // A movie only holds whole frames from power on, so anything else, like
+    // a single step, is refused while recording rather than leaving the
+    // movie unable to repeat the run
+    fn check_not_recording(&self) -> Result<(), MovieError> {
+        match self {
+            MovieMode::Recording(_) => Err(MovieError::Recording),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The running Chip-8 and the browser side of its inputs. Key presses are
+// queued with the time they happened and handed to the instruction running
+// at that time, and timers count emulated frames, so a run only depends on
+// the keys held during each instruction. That is what makes input movies
+// possible.
+pub struct Emulator {
+    chip8: Chip8,
+    rom: Vec<u8>,
+    quirks: Quirks,
+
+    timer: FrameTimer,
+    keypad: SharedKeypad,
+    keyboard: Keyboard,
+    keys: KeyQueue,
+
+    // Frames run since power on
+    frame: u64,
+    movie: MovieMode,
+
+    // (state, frame) kept by the quick save hotkey
+    quick_save: Option<(Snapshot, u64)>,
+}
+
+impl Emulator {
+    // Without `quirks` they're guessed from the ROM, see `guess_quirks`
+    pub fn new(
+        screen: Box<dyn Drawable>,
+        keyboard: Keyboard,
+        rom: &[u8],
+        quirks: Option<Quirks>,
+    ) -> Result<Emulator, Chip8Error> {
+        let timer = FrameTimer::new();
+        let keypad = SharedKeypad::new();
+
+        let mut chip8 = Chip8::new(screen, Box::new(keypad.clone()), Box::new(timer.clone()));
+        match quirks {
+            Some(quirks) => chip8.set_quirks(quirks),
+            None => chip8.guess_quirks_on_load(),
+        }
+        chip8.set_trace_length(TRACE_LENGTH);
+        chip8.init_memory(rom)?;
+
+        // Movies and save states keep the quirks that were guessed
+        Ok(Emulator {
+            rom: rom.to_vec(),
+            quirks: chip8.quirks(),
+            chip8,
+            timer,
+            keypad,
+            keyboard,
+            keys: KeyQueue::new(),
+            frame: 0,
+            movie: MovieMode::Off,
+            quick_save: None,
+        })
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        rom_hash(&self.rom)
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        self.keyboard.take_hotkeys()
+    }
+
+    // Run the frame that took place between the page times `since` and
+    // `until`, in milliseconds
+    pub fn run_frame(&mut self, since: f64, until: f64) -> Result<(), Chip8Error> {
+        // Keys are always taken so they're up to date when a movie ends
+        self.keyboard.take_events(&mut self.keys, until);
+        let keys = self.keys.frame(since, until, STEPS_PER_FRAME);
+
+        let keys = self.movie.frame_keys(self.frame, keys);
+
+        let keypad = &self.keypad;
+        let chip8 = &mut self.chip8;
+        let result = keys.iter().try_for_each(|key| {
+            keypad.set(*key);
+            chip8.step_execution()
+        });
+        self.chip8.vblank();
+        self.timer.tick();
+        self.frame += 1;
+        self.keyboard.set_polled_keys(self.chip8.polled_keys());
+
+        // Hand control back to the keyboard once the movie is over
+        if let MovieMode::Playing(movie) = &self.movie {
+            if self.frame >= movie.frames() {
+                self.movie = MovieMode::Off;
+            }
+        }
+
+        result
+    }
+
+    // Run one instruction while paused, see `Chip8::step_instruction`
+    pub fn step(&mut self) -> Result<(), MovieError> {
+        self.movie.check_not_recording()?;
+
+        // The frame display wait was holding up ends, and timers count it
+        if self.chip8.is_waiting_for_vblank() {
+            self.timer.tick();
+            self.frame += 1;
+        }
+
+        Ok(self.chip8.step_instruction()?)
+    }
+
+    // Power cycle the machine with the given seed
+    pub fn reset(&mut self, seed: u64) -> Result<(), Chip8Error> {
+        self.chip8.set_quirks(self.quirks);
+        self.chip8.set_seed(seed);
+        self.chip8.reset();
+        self.chip8.init_memory(&self.rom)?;
+        self.frame = 0;
+
+        Ok(())
+    }
+
+    // Reset from the page. This ends a movie being played back, but is
+    // refused while recording since the movie couldn't repeat it.
+    pub fn restart(&mut self) -> Result<(), MovieError> {
+        self.movie.check_not_recording()?;
+
+        self.movie = MovieMode::Off;
+        Ok(self.reset(self.chip8.seed())?)
+    }
+
+    pub fn quick_save(&mut self) {
+        self.quick_save = Some((self.chip8.snapshot(), self.frame));
+    }
+
+    // Returns false if nothing was saved. Like `restart` this ends a movie
+    // being played back and is refused while recording.
+    pub fn quick_load(&mut self) -> Result<bool, MovieError> {
+        self.movie.check_not_recording()?;
+
+        match &self.quick_save {
+            Some((snapshot, frame)) => {
+                self.chip8.restore(snapshot);
+                self.frame = *frame;
+                self.movie = MovieMode::Off;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // `time` is stored with the state, see `SaveState`
+    pub fn save_state(&self, time: u64) -> SaveState {
+        SaveState::new(
+            &self.rom,
+            self.frame,
+            time,
+            self.quirks,
+            self.chip8.snapshot(),
+        )
+    }
+
+    // Like `restart` this ends a movie being played back and is refused
+    // while recording
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        if !state.matches_rom(&self.rom) {
+            return Err(SaveStateError::RomMismatch);
+        }
+        self.movie
+            .check_not_recording()
+            .map_err(|_| SaveStateError::RecordingMovie)?;
+
+        self.quirks = state.quirks;
+        self.chip8.set_quirks(state.quirks);
+        self.chip8.restore(&state.snapshot);
+        self.frame = state.frame;
+        self.movie = MovieMode::Off;
+
+        Ok(())
+    }
+
+    // Carry on from `address`, to skip an instruction that failed. Refused
+    // while recording like `step`.
+    pub fn skip_to(&mut self, address: u16) -> Result<(), MovieError> {
+        self.movie.check_not_recording()?;
+
+        self.chip8.jump(address);
+        Ok(())
+    }
+
+    // Everything about `error`, which should have just come from `run_frame`
+    pub fn crash_report(&self, error: &Chip8Error, time: u64) -> CrashReport {
+        CrashReport::new(error, &self.chip8, self.save_state(time))
+    }
+
+    // Movies start from power on so they can be replayed from scratch
+    pub fn start_movie_recording(&mut self) -> Result<(), MovieError> {
+        self.movie.check_not_recording()?;
+
+        let seed = random();
+        self.reset(seed)?;
+        self.movie = MovieMode::Recording(Movie::new(&self.rom, seed, self.quirks));
+
+        Ok(())
+    }
+
+    pub fn stop_movie_recording(&mut self) -> Option<Movie> {
+        match std::mem::replace(&mut self.movie, MovieMode::Off) {
+            MovieMode::Recording(movie) => Some(movie),
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    // Replay a movie from power on, the keyboard is ignored until it ends
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        if !movie.matches_rom(&self.rom) {
+            return Err(MovieError::RomMismatch);
+        }
+        self.movie.check_not_recording()?;
+
+        self.quirks = movie.quirks;
+        self.reset(movie.seed)?;
+        self.movie = MovieMode::Playing(movie);
+
+        Ok(())
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, MovieMode::Playing(_))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headless::Headless;
+    use crate::movie::replay;
+
+    #[test]
+    fn test_recording_refuses_steps() {
+        // Count frames with key 0 down in V2, and every frame in V3 and the
+        // delay timer
+        let rom = [0xE1, 0xA1, 0x72, 0x01, 0x73, 0x01, 0xF3, 0x15, 0x12, 0x00];
+        let mut run = Headless::new(&rom, Quirks::default()).unwrap();
+        let mut movie =
+            MovieMode::Recording(Movie::new(&rom, run.chip8().seed(), Quirks::default()));
+
+        for frame in 0..20 {
+            let pressed = if frame % 3 == 0 { Some(0) } else { None };
+            let keys = movie.frame_keys(frame, vec![pressed; STEPS_PER_FRAME]);
+            run.run_frame_with_keys(&keys).unwrap();
+
+            // Stepping here would run an instruction the movie can't repeat
+            assert!(matches!(
+                movie.check_not_recording(),
+                Err(MovieError::Recording)
+            ));
+        }
+
+        let movie = match movie {
+            MovieMode::Recording(movie) => movie,
+            _ => unreachable!(),
+        };
+        let replayed = replay(&rom, &movie).unwrap();
+        assert_eq!(replayed.frame(), run.frame());
+        assert_eq!(replayed.chip8().registers(), run.chip8().registers());
+        assert_eq!(replayed.chip8().delay_timer(), run.chip8().delay_timer());
+
+        assert!(MovieMode::Playing(movie).check_not_recording().is_ok());
+        assert!(MovieMode::Off.check_not_recording().is_ok());
+    }
+}
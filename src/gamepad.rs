@@ -0,0 +1,199 @@
+// Gamepad input through the browser's Gamepad API. Pads are polled once per
+// frame and every button or stick direction can be bound to a hex key.
+// Buttons and axes are numbered as in the "standard" mapping:
+// https://w3c.github.io/gamepad/#remapping
+use wasm_bindgen::JsCast;
+
+use std::fmt;
+use std::str::FromStr;
+
+// How far a stick has to be pushed before it counts
+const DEADZONE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Button(usize),
+    // (axis, pushed towards positive)
+    Axis(usize, bool),
+}
+
+// Every input that can be bound, in the order they're checked and written out
+const INPUTS: [(&str, Input); 16] = [
+    ("up", Input::Button(12)),
+    ("down", Input::Button(13)),
+    ("left", Input::Button(14)),
+    ("right", Input::Button(15)),
+    ("a", Input::Button(0)),
+    ("b", Input::Button(1)),
+    ("x", Input::Button(2)),
+    ("y", Input::Button(3)),
+    ("left-stick-up", Input::Axis(1, false)),
+    ("left-stick-down", Input::Axis(1, true)),
+    ("left-stick-left", Input::Axis(0, false)),
+    ("left-stick-right", Input::Axis(0, true)),
+    ("right-stick-up", Input::Axis(3, false)),
+    ("right-stick-down", Input::Axis(3, true)),
+    ("right-stick-left", Input::Axis(2, false)),
+    ("right-stick-right", Input::Axis(2, true)),
+];
+
+// Hex key for each of `INPUTS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GamepadBindings {
+    keys: [Option<u8>; INPUTS.len()],
+}
+
+// Directions on the 2/4/6/8 cross most games use, A and B on 5 and 6
+const DEFAULT: GamepadBindings = GamepadBindings {
+    keys: [
+        Some(0x2),
+        Some(0x8),
+        Some(0x4),
+        Some(0x6),
+        Some(0x5),
+        Some(0x6),
+        Some(0x4),
+        Some(0x0),
+        Some(0x2),
+        Some(0x8),
+        Some(0x4),
+        Some(0x6),
+        None,
+        None,
+        None,
+        None,
+    ],
+};
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+impl GamepadBindings {
+    // Key for the first bound input that is held down
+    fn pressed_key(&self, buttons: &[bool], axes: &[f64]) -> Option<u8> {
+        INPUTS
+            .iter()
+            .zip(self.keys.iter())
+            .filter_map(|((_, input), key)| key.map(|key| (input, key)))
+            .find(|(input, _)| match **input {
+                Input::Button(button) => buttons.get(button).copied().unwrap_or(false),
+                Input::Axis(axis, true) => axes.get(axis).is_some_and(|value| *value > DEADZONE),
+                Input::Axis(axis, false) => axes.get(axis).is_some_and(|value| *value < -DEADZONE),
+            })
+            .map(|(_, key)| key)
+    }
+
+    // Key held down on any connected gamepad
+    pub fn poll(&self) -> Option<u8> {
+        let pads = web_sys::window()?.navigator().get_gamepads().ok()?;
+
+        pads.iter()
+            .filter_map(|pad| pad.dyn_into::<web_sys::Gamepad>().ok())
+            .filter(|pad| pad.connected())
+            .find_map(|pad| {
+                let buttons: Vec<bool> = pad
+                    .buttons()
+                    .iter()
+                    .map(|button| {
+                        button
+                            .dyn_into::<web_sys::GamepadButton>()
+                            .is_ok_and(|button| button.pressed())
+                    })
+                    .collect();
+                let axes: Vec<f64> = pad
+                    .axes()
+                    .iter()
+                    .map(|axis| axis.as_f64().unwrap_or(0.0))
+                    .collect();
+
+                self.pressed_key(&buttons, &axes)
+            })
+    }
+}
+
+// Written as a comma separated list of input=key, e.g. "up=2,a=5".
+// Inputs that aren't listed are unbound.
+impl FromStr for GamepadBindings {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bindings = GamepadBindings {
+            keys: [None; INPUTS.len()],
+        };
+
+        for binding in s.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (name, key) = binding.split_once('=').ok_or(())?;
+
+            let index = INPUTS
+                .iter()
+                .position(|(input, _)| *input == name.trim())
+                .ok_or(())?;
+            let key = u8::from_str_radix(key.trim(), 16).map_err(|_| ())?;
+            if key > 0xF {
+                return Err(());
+            }
+
+            bindings.keys[index] = Some(key);
+        }
+
+        Ok(bindings)
+    }
+}
+
+impl fmt::Display for GamepadBindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound: Vec<String> = INPUTS
+            .iter()
+            .zip(self.keys.iter())
+            .filter_map(|((name, _), key)| key.map(|key| format!("{}={:X}", name, key)))
+            .collect();
+
+        write!(f, "{}", bound.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pressed_key() {
+        let mut buttons = [false; 17];
+        let mut axes = [0.0; 4];
+        assert_eq!(DEFAULT.pressed_key(&buttons, &axes), None);
+
+        buttons[0] = true;
+        assert_eq!(DEFAULT.pressed_key(&buttons, &axes), Some(0x5));
+
+        // D-pad is checked before the face buttons
+        buttons[13] = true;
+        assert_eq!(DEFAULT.pressed_key(&buttons, &axes), Some(0x8));
+
+        // Small stick movements are ignored
+        let buttons = [false; 17];
+        axes[0] = -0.3;
+        assert_eq!(DEFAULT.pressed_key(&buttons, &axes), None);
+        axes[0] = -0.9;
+        assert_eq!(DEFAULT.pressed_key(&buttons, &axes), Some(0x4));
+
+        // Pads with fewer buttons and axes than the standard mapping
+        assert_eq!(DEFAULT.pressed_key(&[true], &[]), Some(0x5));
+    }
+
+    #[test]
+    fn test_bindings_round_trip() {
+        assert_eq!(DEFAULT.to_string().parse(), Ok(DEFAULT));
+
+        let custom: GamepadBindings = "a=F, up=1".parse().unwrap();
+        assert_eq!(custom.to_string(), "up=1,a=F");
+        assert_eq!(custom.pressed_key(&[true], &[]), Some(0xF));
+
+        assert_eq!("".parse::<GamepadBindings>().unwrap().to_string(), "");
+        assert_eq!("a=10".parse::<GamepadBindings>(), Err(()));
+        assert_eq!("start=1".parse::<GamepadBindings>(), Err(()));
+        assert_eq!("a".parse::<GamepadBindings>(), Err(()));
+    }
+}
@@ -0,0 +1,324 @@
+// Animated GIF recording of the Chip-8 screen.
+//
+// Frames are captured as raw grids every time the screen is presented and
+// only encoded when the recording is finished. Identical frames in a row
+// are merged into one longer frame, so a mostly static game stays small.
+// https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+
+use crate::chip8::{Chip8Error, Quirks, STEPS_PER_FRAME};
+use crate::headless::Headless;
+use crate::screen::{Palette, RawGrid, Rgba, MAX_SCALE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::convert::TryFrom;
+
+use std::collections::HashMap;
+
+// Frames are captured at 60Hz, GIF delays are in hundredths of a second
+const FRAMES_PER_SECOND: u64 = 60;
+
+// Most viewers treat delays under 2/100s as 10/100s, so never go below that
+const MIN_DELAY: u64 = 2;
+
+// Two colours, the smallest code size GIF allows is 2 bits
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODES: u16 = 4096;
+
+struct Frame {
+    grid: RawGrid,
+
+    // Number of 60Hz frames this grid was on screen for
+    duration: u64,
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    frames: Vec<Frame>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder { frames: Vec::new() }
+    }
+
+    // Capture one presented frame
+    pub fn push(&mut self, grid: &RawGrid) {
+        match self.frames.last_mut() {
+            Some(last) if last.grid == *grid => last.duration += 1,
+            _ => self.frames.push(Frame {
+                grid: *grid,
+                duration: 1,
+            }),
+        }
+    }
+
+    // Number of distinct frames, after merging repeats
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Each chip-8 pixel becomes a `scale` x `scale` square, `scale` has to be
+    // from 1 to `MAX_SCALE`
+    pub fn encode(&self, scale: usize, palette: &Palette) -> Result<Vec<u8>, String> {
+        let invalid = || format!("Scale must be from 1 to {}, not {}", MAX_SCALE, scale);
+        if !(1..=MAX_SCALE).contains(&scale) {
+            return Err(invalid());
+        }
+        let width = u16::try_from(SCREEN_WIDTH * scale).map_err(|_| invalid())?;
+        let height = u16::try_from(SCREEN_HEIGHT * scale).map_err(|_| invalid())?;
+
+        let mut gif = Vec::new();
+        gif.extend_from_slice(b"GIF89a");
+
+        // Logical screen descriptor with a two colour global colour table
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        gif.extend_from_slice(&[0x80, 0, 0]);
+        gif.extend_from_slice(&opaque(palette.background()));
+        gif.extend_from_slice(&opaque(palette.foreground()));
+
+        // Loop forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        // Delays are worked out from when each frame should end, so rounding
+        // doesn't build up over a long recording
+        let mut elapsed_frames = 0;
+        let mut elapsed_delay = 0;
+        for frame in self.frames.iter() {
+            elapsed_frames += frame.duration;
+            let end = (elapsed_frames * 100 + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND;
+            let delay = end.saturating_sub(elapsed_delay).max(MIN_DELAY);
+            elapsed_delay += delay;
+
+            // Graphic control extension, leave the frame in place when done
+            let delay = delay.min(u16::MAX as u64) as u16;
+            gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+            gif.extend_from_slice(&delay.to_le_bytes());
+            gif.extend_from_slice(&[0x00, 0x00]);
+
+            // Image descriptor covering the whole screen, no local colour table
+            gif.push(0x2C);
+            gif.extend_from_slice(&[0, 0, 0, 0]);
+            gif.extend_from_slice(&width.to_le_bytes());
+            gif.extend_from_slice(&height.to_le_bytes());
+            gif.push(0x00);
+
+            gif.push(MIN_CODE_SIZE);
+            let data = lzw_encode(&indices(&frame.grid, scale), MIN_CODE_SIZE);
+            for block in data.chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0x00);
+        }
+
+        gif.push(0x3B);
+
+        Ok(gif)
+    }
+}
+
+// Run a ROM without input for a number of frames and record it
+pub fn render_rom(
+    rom: &[u8],
+    frames: usize,
+    quirks: Quirks,
+    scale: usize,
+    palette: &Palette,
+) -> Result<Vec<u8>, String> {
+    let error = |e: Chip8Error| e.to_string();
+    let mut headless = Headless::new(rom, quirks).map_err(error)?;
+    let mut recorder = Recorder::new();
+
+    for _ in 0..frames {
+        headless.run_frame(STEPS_PER_FRAME).map_err(error)?;
+        recorder.push(headless.chip8().screen());
+    }
+
+    recorder.encode(scale, palette)
+}
+
+// GIF has no real transparency between frames, so draw the palette over white
+fn opaque(color: Rgba) -> [u8; 3] {
+    let [r, g, b, a] = color;
+    let over_white =
+        |channel: u8| ((channel as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+
+    [over_white(r), over_white(g), over_white(b)]
+}
+
+// Colour table index of every pixel, left to right, top to bottom
+fn indices(grid: &RawGrid, scale: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * scale * scale);
+
+    for scanline in grid.iter() {
+        let row: Vec<u8> = (0..SCREEN_WIDTH * scale)
+            .map(|x| ((scanline >> (x / scale)) & 0x01) as u8)
+            .collect();
+
+        for _ in 0..scale {
+            pixels.extend_from_slice(&row);
+        }
+    }
+
+    pixels
+}
+
+// Packs variable width codes least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
+
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end + 1;
+
+    writer.write(clear, code_size);
+
+    let mut pixels = indices.iter();
+    let mut prefix = match pixels.next() {
+        Some(pixel) => *pixel as u16,
+        None => {
+            writer.write(end, code_size);
+            return writer.finish();
+        }
+    };
+
+    for &pixel in pixels {
+        if let Some(code) = table.get(&(prefix, pixel)) {
+            prefix = *code;
+            continue;
+        }
+
+        writer.write(prefix, code_size);
+
+        if next_code == MAX_CODES {
+            // Table is full, start over
+            writer.write(clear, code_size);
+            table.clear();
+            code_size = min_code_size + 1;
+            next_code = end + 1;
+        } else {
+            table.insert((prefix, pixel), next_code);
+
+            // The decoder adds codes one step behind us, so the code size grows
+            // once the code just added no longer fits
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            next_code += 1;
+        }
+
+        prefix = pixel as u16;
+    }
+
+    writer.write(prefix, code_size);
+    writer.write(end, code_size);
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_recorder_merges_frames() {
+        let mut recorder = Recorder::new();
+        let mut grid: RawGrid = [0; SCREEN_HEIGHT];
+
+        recorder.push(&grid);
+        recorder.push(&grid);
+        grid[3] = 0xFF;
+        recorder.push(&grid);
+        recorder.push(&grid);
+        recorder.push(&grid);
+
+        assert_eq!(recorder.len(), 2);
+        assert_eq!(recorder.frames[0].duration, 2);
+        assert_eq!(recorder.frames[1].duration, 3);
+    }
+
+    #[test]
+    fn test_encode_scale() {
+        let mut recorder = Recorder::new();
+        recorder.push(&[0; SCREEN_HEIGHT]);
+
+        // Logical screen width and height come straight after the signature
+        let gif = recorder.encode(2, &Palette::default()).unwrap();
+        assert_eq!(gif[6..10], [128, 0, 64, 0]);
+
+        assert!(recorder.encode(0, &Palette::default()).is_err());
+        assert!(recorder.encode(1024, &Palette::default()).is_err());
+    }
+
+    #[test]
+    fn test_opaque() {
+        assert_eq!(opaque([0, 0, 0, 0]), [255, 255, 255]);
+        assert_eq!(opaque([0x12, 0x34, 0x56, 255]), [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn test_lzw_encode() {
+        // Example from the GIF spec walkthrough at
+        // http://www.matthewflickinger.com/lab/whatsinagif/lzw_image_data.asp
+        let rows: [[u8; 10]; 10] = [
+            [1, 1, 1, 1, 1, 2, 2, 2, 2, 2],
+            [1, 1, 1, 1, 1, 2, 2, 2, 2, 2],
+            [1, 1, 1, 1, 1, 2, 2, 2, 2, 2],
+            [1, 1, 1, 0, 0, 0, 0, 2, 2, 2],
+            [1, 1, 1, 0, 0, 0, 0, 2, 2, 2],
+            [2, 2, 2, 0, 0, 0, 0, 1, 1, 1],
+            [2, 2, 2, 0, 0, 0, 0, 1, 1, 1],
+            [2, 2, 2, 2, 2, 1, 1, 1, 1, 1],
+            [2, 2, 2, 2, 2, 1, 1, 1, 1, 1],
+            [2, 2, 2, 2, 2, 1, 1, 1, 1, 1],
+        ];
+        let indices: Vec<u8> = rows.iter().flatten().cloned().collect();
+
+        assert_eq!(
+            lzw_encode(&indices, 2),
+            [
+                0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA,
+                0xA8, 0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01,
+            ]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// Implementations of the emulator traits that don't need a browser,
+// used to run ROMs from native tools and tests. Time only moves
+// forward when the host runs a frame, so runs are repeatable.
+use crate::chip8::traits::Drawable;
+use crate::chip8::{Chip8, Chip8Error, FrameTimer, Quirks, SharedKeypad};
+use crate::screen::{draw_sprite, RawGrid, SCREEN_HEIGHT};
+
+pub struct HeadlessScreen {
+    raw: RawGrid,
+}
+
+impl HeadlessScreen {
+    pub fn new() -> HeadlessScreen {
+        HeadlessScreen {
+            raw: [0; SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Default for HeadlessScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for HeadlessScreen {
+    fn write_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        draw_sprite(&mut self.raw, x, y, sprite)
+    }
+
+    fn grid(&self) -> &RawGrid {
+        &self.raw
+    }
+
+    // Nothing to present to
+    fn flush(&mut self) {}
+
+    fn clear(&mut self) {
+        self.raw = [0; SCREEN_HEIGHT];
+    }
+
+    fn set_grid(&mut self, grid: &RawGrid) {
+        self.raw = *grid;
+    }
+}
+
+// A Chip-8 wired to the headless implementations above
+pub struct Headless {
+    chip8: Chip8,
+
+    timer: FrameTimer,
+    keypad: SharedKeypad,
+
+    frame: u64,
+}
+
+impl Headless {
+    pub fn new(rom: &[u8], quirks: Quirks) -> Result<Headless, Chip8Error> {
+        let timer = FrameTimer::new();
+        let keypad = SharedKeypad::new();
+
+        let mut chip8 = Chip8::new(
+            Box::new(HeadlessScreen::new()),
+            Box::new(keypad.clone()),
+            Box::new(timer.clone()),
+        );
+        chip8.set_quirks(quirks);
+        chip8.init_memory(rom)?;
+
+        Ok(Headless {
+            chip8,
+            timer,
+            keypad,
+            frame: 0,
+        })
+    }
+
+    pub fn set_key(&mut self, key: Option<u8>) {
+        self.keypad.set(key);
+    }
+
+    pub fn run_frame(&mut self, steps: usize) -> Result<(), Chip8Error> {
+        let result = self.chip8.run_frame(steps);
+
+        self.timer.tick();
+        self.frame += 1;
+
+        result
+    }
+
+    // Like `run_frame`, with `keys` giving the key held down during each
+    // instruction of the frame
+    pub fn run_frame_with_keys(&mut self, keys: &[Option<u8>]) -> Result<(), Chip8Error> {
+        let keypad = &self.keypad;
+        let chip8 = &mut self.chip8;
+        let result = keys.iter().try_for_each(|key| {
+            keypad.set(*key);
+            chip8.step_execution()
+        });
+        self.chip8.vblank();
+
+        self.timer.tick();
+        self.frame += 1;
+
+        result
+    }
+
+    // Like `run_frame`, but checks `stop` before every instruction and returns
+    // `true` straight away if it says to stop, leaving the frame unfinished
+    pub fn run_frame_until(
+        &mut self,
+        steps: usize,
+        mut stop: impl FnMut(&Chip8) -> bool,
+    ) -> Result<bool, Chip8Error> {
+        for _ in 0..steps {
+            if stop(&self.chip8) {
+                return Ok(true);
+            }
+            self.chip8.step_execution()?;
+        }
+
+        self.chip8.vblank();
+        self.timer.tick();
+        self.frame += 1;
+
+        Ok(false)
+    }
+
+    // Number of frames run so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+}
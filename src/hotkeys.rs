@@ -0,0 +1,184 @@
+// Keys that control the emulator rather than the game. They're taken out of
+// the keyboard's events before any reach the Chip-8, so a key bound here
+// never presses a hex key.
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    FrameAdvance,
+    Reset,
+    SpeedUp,
+    SlowDown,
+    QuickSave,
+    QuickLoad,
+    Screenshot,
+}
+
+const HOTKEYS: [(&str, Hotkey); 8] = [
+    ("pause", Hotkey::Pause),
+    ("frame-advance", Hotkey::FrameAdvance),
+    ("reset", Hotkey::Reset),
+    ("speed-up", Hotkey::SpeedUp),
+    ("slow-down", Hotkey::SlowDown),
+    ("quick-save", Hotkey::QuickSave),
+    ("quick-load", Hotkey::QuickLoad),
+    ("screenshot", Hotkey::Screenshot),
+];
+
+// None of these are on the default game keys
+const DEFAULT_HOTKEYS: [(&str, Hotkey); 8] = [
+    ("KeyP", Hotkey::Pause),
+    ("Period", Hotkey::FrameAdvance),
+    ("Backspace", Hotkey::Reset),
+    ("Equal", Hotkey::SpeedUp),
+    ("Minus", Hotkey::SlowDown),
+    ("KeyK", Hotkey::QuickSave),
+    ("KeyL", Hotkey::QuickLoad),
+    ("KeyO", Hotkey::Screenshot),
+];
+
+impl Hotkey {
+    // Every hotkey, as written in bindings
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        HOTKEYS.iter().map(|(name, _)| *name)
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HOTKEYS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, hotkey)| *hotkey)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = HOTKEYS
+            .iter()
+            .find(|(_, hotkey)| hotkey == self)
+            .expect("every hotkey has a name");
+
+        write!(f, "{}", name)
+    }
+}
+
+// Physical keys (`KeyboardEvent.code`) bound to hotkeys, one key each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkeys {
+    bindings: Vec<(String, Hotkey)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            bindings: DEFAULT_HOTKEYS
+                .iter()
+                .map(|(code, hotkey)| (code.to_string(), *hotkey))
+                .collect(),
+        }
+    }
+}
+
+impl Hotkeys {
+    pub fn hotkey_for(&self, code: &str) -> Option<Hotkey> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == code)
+            .map(|(_, hotkey)| *hotkey)
+    }
+
+    pub fn code_for(&self, hotkey: Hotkey) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == hotkey)
+            .map(|(code, _)| code.as_str())
+    }
+
+    // Every physical key that's bound to a hotkey
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.bindings.iter().map(|(code, _)| code.as_str())
+    }
+
+    // Replaces the hotkey's old key, and whatever the new key did before
+    pub fn bind(&mut self, code: &str, hotkey: Hotkey) {
+        self.bindings
+            .retain(|(bound_code, bound)| bound_code != code && *bound != hotkey);
+        self.bindings.push((code.to_string(), hotkey));
+    }
+}
+
+// Written as a comma separated list of hotkey=code, e.g. "pause=KeyP".
+// Hotkeys that aren't listed have no key.
+impl FromStr for Hotkeys {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut hotkeys = Hotkeys {
+            bindings: Vec::new(),
+        };
+
+        for binding in s.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (hotkey, code) = binding.split_once('=').ok_or(())?;
+
+            let code = code.trim();
+            if code.is_empty() {
+                return Err(());
+            }
+
+            hotkeys.bind(code, hotkey.trim().parse()?);
+        }
+
+        Ok(hotkeys)
+    }
+}
+
+impl fmt::Display for Hotkeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound: Vec<String> = HOTKEYS
+            .iter()
+            .filter_map(|(name, hotkey)| {
+                self.code_for(*hotkey)
+                    .map(|code| format!("{}={}", name, code))
+            })
+            .collect();
+
+        write!(f, "{}", bound.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hotkeys() {
+        let mut hotkeys = Hotkeys::default();
+        assert_eq!(hotkeys.hotkey_for("KeyP"), Some(Hotkey::Pause));
+        assert_eq!(hotkeys.hotkey_for("KeyX"), None);
+
+        // Moving pause to a key that was in use takes the key over
+        hotkeys.bind("KeyK", Hotkey::Pause);
+        assert_eq!(hotkeys.hotkey_for("KeyP"), None);
+        assert_eq!(hotkeys.hotkey_for("KeyK"), Some(Hotkey::Pause));
+        assert_eq!(hotkeys.code_for(Hotkey::QuickSave), None);
+    }
+
+    #[test]
+    fn test_hotkeys_round_trip() {
+        let hotkeys = Hotkeys::default();
+        assert_eq!(hotkeys.to_string().parse(), Ok(hotkeys));
+
+        let hotkeys: Hotkeys = "reset=F2, pause=Space".parse().unwrap();
+        assert_eq!(hotkeys.to_string(), "pause=Space,reset=F2");
+
+        assert_eq!("rewind=KeyR".parse::<Hotkeys>(), Err(()));
+        assert_eq!("pause=".parse::<Hotkeys>(), Err(()));
+    }
+}
@@ -1,6 +1,15 @@
-use crate::chip8::traits::HexKeyboard;
+use crate::chip8::{KeyEvent, KeyQueue};
+use crate::gamepad::GamepadBindings;
+use crate::hotkeys::{Hotkey, Hotkeys};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
 
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 // Re-exports a class defined in javascript
 
 #[wasm_bindgen(raw_module = "../js/keyboard.ts")]
@@ -8,30 +17,342 @@ extern "C" {
     type KeyboardListener;
 
     #[wasm_bindgen(constructor)]
-    fn new() -> KeyboardListener;
+    fn new(scope: &web_sys::EventTarget) -> KeyboardListener;
+
+    #[wasm_bindgen(method)]
+    fn detach(this: &KeyboardListener);
+
+    #[wasm_bindgen(method, js_name = hasFocus)]
+    fn has_focus(this: &KeyboardListener) -> bool;
+
+    #[wasm_bindgen(method, js_name = takeEvents)]
+    fn take_events(this: &KeyboardListener) -> js_sys::Array;
+
+    #[wasm_bindgen(method, js_name = setHotkeyCodes)]
+    fn set_hotkey_codes(this: &KeyboardListener, codes: js_sys::Array);
+
+    #[wasm_bindgen(method, js_name = takeHotkeys)]
+    fn take_hotkeys(this: &KeyboardListener) -> js_sys::Array;
+
+    #[wasm_bindgen(method)]
+    fn capture(this: &KeyboardListener, callback: &js_sys::Function);
+
+    // A key going up or down, either a physical key (`code`) or a
+    // Chip-8 key from another input (`key`)
+    type ListenerEvent;
+
+    #[wasm_bindgen(method, getter)]
+    fn time(this: &ListenerEvent) -> f64;
+
+    #[wasm_bindgen(method, getter)]
+    fn code(this: &ListenerEvent) -> Option<String>;
+
+    #[wasm_bindgen(method, getter)]
+    fn key(this: &ListenerEvent) -> Option<u8>;
 
-    #[wasm_bindgen(method, getter = keyPressed)]
-    fn pressed_key(this: &KeyboardListener) -> i8;
+    #[wasm_bindgen(method, getter)]
+    fn pressed(this: &ListenerEvent) -> bool;
 }
 
+#[wasm_bindgen(raw_module = "../js/touch_keypad.ts")]
+extern "C" {
+    type TouchKeypad;
+
+    #[wasm_bindgen(constructor)]
+    fn new(
+        listener: &KeyboardListener,
+        container: Option<web_sys::Element>,
+        polled_checkbox: Option<web_sys::Element>,
+    ) -> TouchKeypad;
+
+    #[wasm_bindgen(method, js_name = detach)]
+    fn detach_keypad(this: &TouchKeypad);
+
+    #[wasm_bindgen(method, js_name = setPolledKeys)]
+    fn set_polled_keys(this: &TouchKeypad, polled_keys: u16);
+}
+
+// Physical keys (`KeyboardEvent.code`) bound to hex keys. Any number of
+// physical keys can press the same hex key. A key bound to `None` does
+// nothing, which lets overrides take a key away.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyBindings {
+    bindings: Vec<(String, Option<u8>)>,
+}
+
+// The left hand side of a QWERTY keyboard, laid out like the COSMAC keypad
+#[rustfmt::skip]
+const DEFAULT_BINDINGS: [(&str, u8); 16] = [
+    ("Digit1", 0x1), ("Digit2", 0x2), ("Digit3", 0x3), ("Digit4", 0xC),
+    ("KeyQ", 0x4), ("KeyW", 0x5), ("KeyE", 0x6), ("KeyR", 0xD),
+    ("KeyA", 0x7), ("KeyS", 0x8), ("KeyD", 0x9), ("KeyF", 0xE),
+    ("KeyZ", 0xA), ("KeyX", 0x0), ("KeyC", 0xB), ("KeyV", 0xF),
+];
+
+impl KeyBindings {
+    pub fn qwerty() -> KeyBindings {
+        KeyBindings {
+            bindings: DEFAULT_BINDINGS
+                .iter()
+                .map(|(code, key)| (code.to_string(), Some(*key)))
+                .collect(),
+        }
+    }
+
+    // Hex key for a physical key, later bindings win
+    pub fn key_for(&self, code: &str) -> Option<u8> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| bound == code)
+            .and_then(|(_, key)| *key)
+    }
+
+    // Physical keys that press `key`
+    pub fn codes_for(&self, key: u8) -> Vec<&str> {
+        self.bindings
+            .iter()
+            .filter(|(code, _)| self.key_for(code) == Some(key))
+            .map(|(code, _)| code.as_str())
+            .collect()
+    }
+
+    // A physical key only presses one hex key, so an old binding is replaced
+    pub fn bind(&mut self, code: &str, key: Option<u8>) {
+        self.bindings.retain(|(bound, _)| bound != code);
+        self.bindings.push((code.to_string(), key));
+    }
+
+    // These bindings with `overrides` taking priority
+    pub fn with_overrides(&self, overrides: &KeyBindings) -> KeyBindings {
+        let mut bindings = self.clone();
+        for (code, key) in &overrides.bindings {
+            bindings.bind(code, *key);
+        }
+
+        bindings
+    }
+}
+
+// Written as a comma separated list of code=key, e.g. "KeyX=0,KeyW=5".
+// "-" instead of a key leaves the physical key unbound, e.g. "KeyW=-".
+impl FromStr for KeyBindings {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bindings = KeyBindings::default();
+
+        for binding in s.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (code, key) = binding.split_once('=').ok_or(())?;
+
+            let code = code.trim();
+            if code.is_empty() {
+                return Err(());
+            }
+
+            let key = match key.trim() {
+                "-" => None,
+                key => match u8::from_str_radix(key, 16) {
+                    Ok(key) if key <= 0xF => Some(key),
+                    _ => return Err(()),
+                },
+            };
+
+            bindings.bind(code, key);
+        }
+
+        Ok(bindings)
+    }
+}
+
+impl fmt::Display for KeyBindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bound: Vec<String> = self
+            .bindings
+            .iter()
+            .map(|(code, key)| match key {
+                Some(key) => format!("{}={:X}", code, key),
+                None => format!("{}=-", code),
+            })
+            .collect();
+
+        write!(f, "{}", bound.join(","))
+    }
+}
+
+// Keys pressed on the keyboard, the touch keypad or any connected gamepad
 pub struct Keyboard {
     listener: KeyboardListener,
+    touch: TouchKeypad,
+
+    // Shared with the page so bindings can be changed while running
+    bindings: Rc<RefCell<KeyBindings>>,
+    gamepad: Rc<Cell<GamepadBindings>>,
+    hotkeys: Rc<RefCell<Hotkeys>>,
+
+    // Hotkeys the listener was last told to keep from the game
+    listener_hotkeys: Option<Hotkeys>,
+    // Hex key each held physical key pressed, so it's released even if
+    // the bindings change while it's down
+    held_codes: HashMap<String, u8>,
+    gamepad_key: Option<u8>,
+}
+
+// Where a keyboard takes its input from on the page
+pub struct KeyboardScope {
+    // Keys are only heard while focus is inside this, the window hears every
+    // key on the page
+    pub focus: web_sys::EventTarget,
+    // Element the touch keypad is added to, and the checkbox that hides keys
+    // the ROM never checks
+    pub touch_keypad: Option<web_sys::Element>,
+    pub polled_keys_checkbox: Option<web_sys::Element>,
 }
 
 impl Keyboard {
-    pub fn new() -> Self {
+    pub fn new(
+        scope: KeyboardScope,
+        bindings: Rc<RefCell<KeyBindings>>,
+        gamepad: Rc<Cell<GamepadBindings>>,
+        hotkeys: Rc<RefCell<Hotkeys>>,
+    ) -> Self {
+        // The touch keypad presses keys through the keyboard listener
+        let listener = KeyboardListener::new(&scope.focus);
+        let touch = TouchKeypad::new(&listener, scope.touch_keypad, scope.polled_keys_checkbox);
+
         Self {
-            listener: KeyboardListener::new(),
+            listener,
+            touch,
+            bindings,
+            gamepad,
+            hotkeys,
+            listener_hotkeys: None,
+            held_codes: HashMap::new(),
+            gamepad_key: None,
         }
     }
-}
 
-impl HexKeyboard for Keyboard {
-    fn pressed_key(&self) -> Option<u8> {
-        let key = self.listener.pressed_key();
-        return match key {
-            0x0..=0xF => Some(key as u8),
-            _ => None,
+    // Move every key press and release since the last call to `queue`.
+    // Gamepads have no events, they're polled and changes are timed `now`.
+    // Like the keyboard they only count while the scope has focus.
+    pub fn take_events(&mut self, queue: &mut KeyQueue, now: f64) {
+        for event in self.listener.take_events().iter() {
+            let event: ListenerEvent = event.unchecked_into();
+            let pressed = event.pressed();
+            let key = match (event.code(), event.key()) {
+                (Some(code), _) if pressed => {
+                    let key = self.bindings.borrow().key_for(&code);
+                    if let Some(key) = key {
+                        self.held_codes.insert(code, key);
+                    }
+                    key
+                }
+                (Some(code), _) => self.held_codes.remove(&code),
+                (None, key) => key.filter(|key| *key <= 0xF),
+            };
+
+            if let Some(key) = key {
+                queue.push(KeyEvent {
+                    time: event.time(),
+                    key,
+                    pressed,
+                });
+            }
+        }
+
+        let gamepad_key = match self.listener.has_focus() {
+            true => self.gamepad.get().poll(),
+            false => None,
         };
+        if gamepad_key != self.gamepad_key {
+            let changes = [(self.gamepad_key, false), (gamepad_key, true)];
+            for (key, pressed) in changes {
+                if let Some(key) = key {
+                    queue.push(KeyEvent {
+                        time: now,
+                        key,
+                        pressed,
+                    });
+                }
+            }
+            self.gamepad_key = gamepad_key;
+        }
+    }
+
+    // Hotkeys pressed since the last call
+    pub fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        let hotkeys = self.hotkeys.borrow();
+
+        // Hotkeys are filtered out by the listener, so it needs to know
+        // about any that were rebound
+        if self.listener_hotkeys.as_ref() != Some(&*hotkeys) {
+            let codes = hotkeys.codes().map(JsValue::from).collect();
+            self.listener.set_hotkey_codes(codes);
+            self.listener_hotkeys = Some(hotkeys.clone());
+        }
+
+        self.listener
+            .take_hotkeys()
+            .iter()
+            .filter_map(|code| hotkeys.hotkey_for(&code.as_string()?))
+            .collect()
+    }
+
+    // Lets the touch keypad hide keys the ROM never checks
+    pub fn set_polled_keys(&self, polled_keys: u16) {
+        self.touch.set_polled_keys(polled_keys);
+    }
+
+    // The next physical key pressed goes to `callback` instead of the game
+    pub fn capture_next_key(&self, callback: impl FnOnce(String) + 'static) {
+        let callback = Closure::once_into_js(callback);
+        self.listener.capture(callback.unchecked_ref());
+    }
+}
+
+// Several emulators can share a page, one that's gone shouldn't keep
+// listening for keys
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        self.listener.detach();
+        self.touch.detach_keypad();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_bindings() {
+        let mut bindings = KeyBindings::qwerty();
+        assert_eq!(bindings.key_for("KeyX"), Some(0x0));
+        assert_eq!(bindings.key_for("KeyP"), None);
+
+        // Several keys for one hex key, and rebinding moves a key
+        bindings.bind("ArrowUp", Some(0x5));
+        bindings.bind("KeyX", Some(0x5));
+        assert_eq!(bindings.codes_for(0x5), ["KeyW", "ArrowUp", "KeyX"]);
+        assert!(bindings.codes_for(0x0).is_empty());
+
+        // Overrides can rebind and unbind
+        let overrides: KeyBindings = "KeyW=-, KeyQ=8".parse().unwrap();
+        let merged = KeyBindings::qwerty().with_overrides(&overrides);
+        assert_eq!(merged.key_for("KeyW"), None);
+        assert_eq!(merged.key_for("KeyQ"), Some(0x8));
+        assert_eq!(merged.codes_for(0x8), ["KeyS", "KeyQ"]);
+    }
+
+    #[test]
+    fn test_key_bindings_round_trip() {
+        let qwerty = KeyBindings::qwerty();
+        assert_eq!(qwerty.to_string().parse(), Ok(qwerty));
+
+        let bindings: KeyBindings = "Numpad5=5,KeyW=-".parse().unwrap();
+        assert_eq!(bindings.to_string(), "Numpad5=5,KeyW=-");
+
+        assert_eq!("KeyW=10".parse::<KeyBindings>(), Err(()));
+        assert_eq!("=1".parse::<KeyBindings>(), Err(()));
+        assert_eq!("KeyW".parse::<KeyBindings>(), Err(()));
     }
 }
@@ -1,12 +1,26 @@
-mod chip8;
-mod countdown;
+pub mod analysis;
+pub mod chip8;
+pub mod compare;
+pub mod crash;
+mod debugger;
+mod emulator;
+mod gamepad;
+pub mod gif;
+pub mod headless;
+mod hotkeys;
 mod keyboard;
-mod screen;
+pub mod movie;
+pub mod png;
+pub mod save_state;
+pub mod screen;
 
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 use web_sys::console;
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use url::Url;
 
@@ -14,12 +28,23 @@ extern crate console_error_panic_hook;
 use std::panic;
 
 mod start;
-use start::run_emulator;
+use start::{run_comparison, run_emulator, ComparisonView, Instance, Options, View};
+
+use gif::Recorder;
+
+use chip8::Quirks;
+use emulator::Emulator;
+use gamepad::GamepadBindings;
+use hotkeys::{Hotkey, Hotkeys};
+use keyboard::{KeyBindings, KeyboardScope};
+use movie::Movie;
+use save_state::SaveState;
+use screen::{Palette, Persistence};
 
 #[wasm_bindgen(raw_module = "../js/panic_handler.ts")]
 extern "C" {
     #[wasm_bindgen(js_name = panicHandler)]
-    fn panic_handler();
+    fn panic_handler(message: &str);
 }
 
 #[macro_use]
@@ -29,30 +54,743 @@ extern crate rust_embed;
 #[folder = "static/roms/"]
 struct Asset;
 
+// The chosen palette is remembered between visits
+const PALETTE_STORAGE_KEY: &str = "chip8-palette";
+
+// Gamepad bindings are remembered per ROM, under this prefix and the ROM name
+const GAMEPAD_STORAGE_PREFIX: &str = "chip8-gamepad/";
+
+// Save states are stored under this prefix, the ROM's hash and the slot
+const STATE_STORAGE_PREFIX: &str = "chip8-state/";
+const SAVE_SLOTS: u8 = 10;
+
+// Slot written when the page is closed
+const AUTO_SAVE_SLOT: &str = "auto";
+
+// Hotkeys are the same for every ROM
+const HOTKEYS_STORAGE_KEY: &str = "chip8-hotkeys";
+
+// Key bindings for every ROM, overrides for a ROM are stored under this
+// key, a slash and the ROM name
+const KEYS_STORAGE_KEY: &str = "chip8-keys";
+
+thread_local! {
+    // Shared with the running emulator's canvas
+    static PALETTE: Rc<Cell<Palette>> = Rc::new(Cell::new(load_palette()));
+
+    // The page's main emulator, on the `#canvas` element
+    static EMULATOR: RefCell<Option<Rc<RefCell<Emulator>>>> = const { RefCell::new(None) };
+
+    static RECORDER: Rc<RefCell<Option<Recorder>>> = Rc::new(RefCell::new(None));
+
+    // Shared with the running emulator's keyboard
+    static GAMEPAD: Rc<Cell<GamepadBindings>> = Rc::new(Cell::new(load_gamepad_bindings()));
+
+    // Key bindings for every ROM and the running ROM's overrides
+    static KEY_BINDINGS: RefCell<KeyBindings> = RefCell::new(load_key_bindings(false));
+    static ROM_KEY_BINDINGS: RefCell<KeyBindings> = RefCell::new(load_key_bindings(true));
+
+    // Both of the above merged, shared with the running emulator's keyboard
+    static KEYS: Rc<RefCell<KeyBindings>> = Rc::new(RefCell::new(merged_key_bindings()));
+
+    static HOTKEYS: Rc<RefCell<Hotkeys>> = Rc::new(RefCell::new(load_hotkeys()));
+}
+
 #[wasm_bindgen(start)]
 pub fn start() -> Result<(), JsValue> {
     // Catch any panics that occur and report them to javascript
     panic::set_hook(Box::new(|info| {
         console::error_1(&JsValue::from(format!("{}", info)));
-        panic_handler();
+        panic_handler(&info.to_string());
     }));
 
-    let rom = Asset::get(&format!("{}.ch8", get_rom_name()))
-        .expect("Could not get ROM")
-        .into_owned();
+    // Pages with only embedded emulators don't have a main one
+    let document = web_sys::window().unwrap().document().unwrap();
+    let canvas = match document.get_element_by_id("canvas") {
+        Some(canvas) => canvas.dyn_into().map_err(|_| "#canvas is not a canvas")?,
+        None => return Ok(()),
+    };
+
+    let rom = page_rom();
+
+    let options = Options {
+        quirks: get_quirks()?,
+        persistence: get_persistence(),
+        palette: PALETTE.with(|palette| palette.clone()),
+        recorder: RECORDER.with(|recorder| recorder.clone()),
+        keys: KEYS.with(|keys| keys.clone()),
+        gamepad: GAMEPAD.with(|gamepad| gamepad.clone()),
+        hotkeys: HOTKEYS.with(|hotkeys| hotkeys.clone()),
+    };
 
-    match run_emulator(&rom[..]) {
-        Ok(_) => Ok(()),
+    // The main emulator hears keys pressed anywhere on the page
+    let view = View {
+        canvas,
+        keyboard: KeyboardScope {
+            focus: web_sys::window().unwrap().into(),
+            touch_keypad: document.get_element_by_id("touch-keypad"),
+            polled_keys_checkbox: document.get_element_by_id("touch-keypad-polled"),
+        },
+        debugger: true,
+    };
+
+    // It runs for as long as the page is open
+    match run_emulator(&rom[..], options, view) {
+        Ok(running) => {
+            EMULATOR.with(|emulator| emulator.replace(Some(running.emulator().clone())));
+            Ok(())
+        }
         Err(e) => Err(JsValue::from(e)),
     }
 }
 
-fn get_rom_name() -> String {
-    // get rom name from url query defaulting to "test_opcode"
+// An emulator embedded in a page next to any others, e.g. a live example
+// in the docs. It draws on `canvas` and hears keys while the canvas has
+// focus. Key bindings and hotkeys are the ones set for every ROM, quirks
+// are a comma separated list, see `Quirks`, or "auto" to guess them.
+#[wasm_bindgen]
+pub struct EmbeddedEmulator {
+    instance: Instance,
+}
+
+#[wasm_bindgen]
+impl EmbeddedEmulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        canvas: web_sys::HtmlCanvasElement,
+        rom: &[u8],
+        quirks: &str,
+    ) -> Result<EmbeddedEmulator, JsValue> {
+        let quirks = match quirks {
+            "auto" => None,
+            _ => Some(
+                quirks
+                    .parse()
+                    .map_err(|_| JsValue::from(format!("Invalid quirks: {}", quirks)))?,
+            ),
+        };
+
+        // Keys only reach a canvas that can take focus
+        canvas.set_attribute("tabindex", "0")?;
+
+        let options = Options {
+            quirks,
+            persistence: Persistence::Off,
+            palette: PALETTE.with(|palette| palette.clone()),
+            recorder: Rc::new(RefCell::new(None)),
+            keys: Rc::new(RefCell::new(
+                KEY_BINDINGS.with(|bindings| bindings.borrow().clone()),
+            )),
+            gamepad: Rc::new(Cell::new(GamepadBindings::default())),
+            hotkeys: HOTKEYS.with(|hotkeys| hotkeys.clone()),
+        };
+
+        let view = View {
+            keyboard: KeyboardScope {
+                focus: canvas.clone().into(),
+                touch_keypad: None,
+                polled_keys_checkbox: None,
+            },
+            canvas,
+            debugger: false,
+        };
+
+        let instance = run_emulator(rom, options, view)?;
+        Ok(EmbeddedEmulator { instance })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.instance.is_paused()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.instance.set_paused(paused);
+    }
+
+    pub fn reset(&self) -> Result<(), JsValue> {
+        self.instance
+            .emulator()
+            .borrow_mut()
+            .restart()
+            .map_err(|e| JsValue::from(e.to_string()))
+    }
+}
+
+// Freeing the emulator from javascript takes it off the page
+impl Drop for EmbeddedEmulator {
+    fn drop(&mut self) {
+        self.instance.stop();
+    }
+}
+
+// The page's ROM on two machines side by side, with different quirks and the
+// same keys, see `Comparison`. `divergence` shows the first instruction that
+// left them in different states. Keys reach both while focus is inside
+// `container`.
+#[wasm_bindgen]
+pub struct QuirkComparison {
+    stopped: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl QuirkComparison {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        container: web_sys::Element,
+        left: web_sys::HtmlCanvasElement,
+        right: web_sys::HtmlCanvasElement,
+        divergence: web_sys::Element,
+        left_quirks: &str,
+        right_quirks: &str,
+    ) -> Result<QuirkComparison, JsValue> {
+        let quirks = [left_quirks, right_quirks].map(|quirks| {
+            quirks
+                .parse::<Quirks>()
+                .map_err(|_| JsValue::from(format!("Invalid quirks: {}", quirks)))
+        });
+        let [left_quirks, right_quirks] = quirks;
+
+        let options = Options {
+            quirks: None,
+            persistence: get_persistence(),
+            palette: PALETTE.with(|palette| palette.clone()),
+            recorder: Rc::new(RefCell::new(None)),
+            keys: KEYS.with(|keys| keys.clone()),
+            gamepad: GAMEPAD.with(|gamepad| gamepad.clone()),
+            hotkeys: HOTKEYS.with(|hotkeys| hotkeys.clone()),
+        };
+
+        let view = ComparisonView {
+            canvases: [left, right],
+            keyboard: KeyboardScope {
+                focus: container.into(),
+                touch_keypad: None,
+                polled_keys_checkbox: None,
+            },
+            divergence,
+        };
+
+        let stopped = Rc::new(Cell::new(false));
+        run_comparison(
+            &page_rom(),
+            [left_quirks?, right_quirks?],
+            options,
+            view,
+            stopped.clone(),
+        )?;
+
+        Ok(QuirkComparison { stopped })
+    }
+}
+
+// Freeing the comparison from javascript stops it
+impl Drop for QuirkComparison {
+    fn drop(&mut self) {
+        self.stopped.set(true);
+    }
+}
+
+// PNG of the current screen in the current palette, `scale` pixels per chip-8 pixel
+#[wasm_bindgen]
+pub fn screenshot_png(scale: usize) -> Result<Vec<u8>, JsValue> {
+    with_emulator(|emulator| {
+        let palette = PALETTE.with(|palette| palette.get());
+        png::encode_grid(emulator.chip8().screen(), scale, &palette).map_err(JsValue::from)
+    })
+}
+
+// Restarts the ROM and records every key pressed from then on
+#[wasm_bindgen]
+pub fn start_movie_recording() -> Result<(), JsValue> {
+    with_emulator(|emulator| {
+        emulator
+            .start_movie_recording()
+            .map_err(|e| JsValue::from(e.to_string()))
+    })
+}
+
+// Finish recording and return the movie file
+#[wasm_bindgen]
+pub fn stop_movie_recording() -> Result<String, JsValue> {
+    with_emulator(|emulator| match emulator.stop_movie_recording() {
+        Some(movie) => Ok(movie.to_string()),
+        None => Err(JsValue::from("Not recording a movie")),
+    })
+}
+
+// Restarts the ROM and plays back a movie file recorded with it
+#[wasm_bindgen]
+pub fn play_movie(movie: &str) -> Result<(), JsValue> {
+    let movie: Movie = movie
+        .parse()
+        .map_err(|e: movie::MovieError| e.to_string())?;
+
+    with_emulator(|emulator| {
+        emulator
+            .play_movie(movie)
+            .map_err(|e| JsValue::from(e.to_string()))
+    })
+}
+
+#[wasm_bindgen]
+pub fn is_playing_movie() -> bool {
+    with_emulator(|emulator| Ok(emulator.is_playing_movie())).unwrap_or(false)
+}
+
+#[wasm_bindgen]
+pub fn save_slots() -> u8 {
+    SAVE_SLOTS
+}
+
+// Save the running ROM's state to one of `SAVE_SLOTS` slots
+#[wasm_bindgen]
+pub fn save_state_to_slot(slot: u8) -> Result<(), JsValue> {
+    write_state(&numbered_slot(slot)?)
+}
+
+#[wasm_bindgen]
+pub fn load_state_from_slot(slot: u8) -> Result<(), JsValue> {
+    load_state(&numbered_slot(slot)?)
+}
+
+// When the slot was saved in milliseconds since the unix epoch, if it was
+#[wasm_bindgen]
+pub fn save_slot_time(slot: u8) -> Result<Option<f64>, JsValue> {
+    Ok(read_state(&numbered_slot(slot)?)?.map(|state| state.time as f64))
+}
+
+// PNG of the screen when the slot was saved, in the current palette
+#[wasm_bindgen]
+pub fn save_slot_thumbnail(slot: u8, scale: usize) -> Result<Option<Vec<u8>>, JsValue> {
+    let palette = PALETTE.with(|palette| palette.get());
+
+    read_state(&numbered_slot(slot)?)?
+        .map(|state| png::encode_grid(state.screen(), scale, &palette))
+        .transpose()
+        .map_err(JsValue::from)
+}
+
+// The slot as a save state file, see `SaveState` for the format
+#[wasm_bindgen]
+pub fn export_save_slot(slot: u8) -> Result<Option<String>, JsValue> {
+    read_state(&numbered_slot(slot)?)?
+        .map(|state| state.to_text())
+        .transpose()
+        .map_err(|e| JsValue::from(e.to_string()))
+}
+
+// Store a save state file in a slot, it has to be for the running ROM
+#[wasm_bindgen]
+pub fn import_save_slot(slot: u8, state: &str) -> Result<(), JsValue> {
+    let slot = numbered_slot(slot)?;
+    let state: SaveState = state
+        .parse()
+        .map_err(|e: save_state::SaveStateError| e.to_string())?;
+
+    with_emulator(|emulator| {
+        if state.rom_hash != emulator.rom_hash() {
+            return Err(JsValue::from(
+                save_state::SaveStateError::RomMismatch.to_string(),
+            ));
+        }
+
+        store_state(emulator.rom_hash(), &slot, &state)
+    })
+}
+
+// Called when the page is closed so the next visit can resume
+#[wasm_bindgen]
+pub fn auto_save() -> Result<(), JsValue> {
+    write_state(AUTO_SAVE_SLOT)
+}
+
+// When the running ROM was last left, if it's been played before
+#[wasm_bindgen]
+pub fn auto_save_time() -> Result<Option<f64>, JsValue> {
+    Ok(read_state(AUTO_SAVE_SLOT)?.map(|state| state.time as f64))
+}
+
+#[wasm_bindgen]
+pub fn resume_auto_save() -> Result<(), JsValue> {
+    load_state(AUTO_SAVE_SLOT)
+}
+
+fn numbered_slot(slot: u8) -> Result<String, JsValue> {
+    match slot {
+        0..SAVE_SLOTS => Ok(slot.to_string()),
+        _ => Err(JsValue::from(format!("No save slot {}", slot))),
+    }
+}
+
+fn state_storage_key(rom_hash: u64, slot: &str) -> String {
+    format!("{}{:016x}/{}", STATE_STORAGE_PREFIX, rom_hash, slot)
+}
+
+fn write_state(slot: &str) -> Result<(), JsValue> {
+    with_emulator(|emulator| {
+        let state = emulator.save_state(js_sys::Date::now() as u64);
+        store_state(emulator.rom_hash(), slot, &state)
+    })
+}
+
+fn store_state(rom_hash: u64, slot: &str, state: &SaveState) -> Result<(), JsValue> {
+    let storage = local_storage().ok_or_else(|| JsValue::from("Storage is not available"))?;
+    let text = state.to_text().map_err(|e| JsValue::from(e.to_string()))?;
+    storage.set_item(&state_storage_key(rom_hash, slot), &text)
+}
+
+fn read_state(slot: &str) -> Result<Option<SaveState>, JsValue> {
+    let rom_hash = with_emulator(|emulator| Ok(emulator.rom_hash()))?;
+    let saved = local_storage()
+        .and_then(|storage| storage.get_item(&state_storage_key(rom_hash, slot)).ok()?);
+
+    match saved {
+        Some(state) => state
+            .parse()
+            .map(Some)
+            .map_err(|e: save_state::SaveStateError| JsValue::from(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn load_state(slot: &str) -> Result<(), JsValue> {
+    let state = read_state(slot)?.ok_or_else(|| JsValue::from("Nothing saved in that slot"))?;
+
+    with_emulator(|emulator| {
+        emulator
+            .load_state(&state)
+            .map_err(|e| JsValue::from(e.to_string()))
+    })
+}
+
+fn with_emulator<T>(f: impl FnOnce(&mut Emulator) -> Result<T, JsValue>) -> Result<T, JsValue> {
+    EMULATOR.with(|emulator| match &*emulator.borrow() {
+        Some(running) => f(&mut running.borrow_mut()),
+        None => Err(JsValue::from("Emulator is not running")),
+    })
+}
+
+// Capture every frame until `stop_recording`
+#[wasm_bindgen]
+pub fn start_recording() {
+    RECORDER.with(|recorder| recorder.replace(Some(Recorder::new())));
+}
+
+// Finish the recording and encode it as an animated GIF
+#[wasm_bindgen]
+pub fn stop_recording(scale: usize) -> Result<Vec<u8>, JsValue> {
+    let palette = PALETTE.with(|palette| palette.get());
+
+    RECORDER.with(|recorder| {
+        // Only stop once the GIF is made, so a bad scale doesn't lose the recording
+        let gif = match &*recorder.borrow() {
+            Some(recorder) => recorder.encode(scale, &palette).map_err(JsValue::from)?,
+            None => return Err(JsValue::from("Not recording")),
+        };
+        recorder.replace(None);
+
+        Ok(gif)
+    })
+}
+
+// Accepts a preset name or a comma separated list of "#RRGGBB" colours,
+// see `Palette` for details
+#[wasm_bindgen]
+pub fn set_palette(palette: &str) -> Result<(), JsValue> {
+    let palette: Palette = palette
+        .parse()
+        .map_err(|_| JsValue::from(format!("Invalid palette: {}", palette)))?;
+
+    PALETTE.with(|current| current.set(palette));
+
+    if let Some(storage) = local_storage() {
+        storage.set_item(PALETTE_STORAGE_KEY, &palette.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_palette() -> String {
+    PALETTE.with(|palette| palette.get().to_string())
+}
+
+#[wasm_bindgen]
+pub fn palette_presets() -> js_sys::Array {
+    screen::PALETTE_PRESETS
+        .iter()
+        .map(|(name, _)| JsValue::from(*name))
+        .collect()
+}
+
+// Bindings for the running ROM as a comma separated list of input=key,
+// e.g. "up=2,a=5", see `GamepadBindings` for the input names
+#[wasm_bindgen]
+pub fn set_gamepad_bindings(bindings: &str) -> Result<(), JsValue> {
+    let bindings: GamepadBindings = bindings
+        .parse()
+        .map_err(|_| JsValue::from(format!("Invalid gamepad bindings: {}", bindings)))?;
+
+    GAMEPAD.with(|current| current.set(bindings));
+
+    if let Some(storage) = local_storage() {
+        let key = format!("{}{}", GAMEPAD_STORAGE_PREFIX, get_rom_name());
+        storage.set_item(&key, &bindings.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_gamepad_bindings() -> String {
+    GAMEPAD.with(|gamepad| gamepad.get().to_string())
+}
+
+// Key bindings for every ROM, or the overrides for the running ROM, as a
+// comma separated list of code=key, e.g. "KeyX=0,ArrowUp=5". Codes are
+// `KeyboardEvent.code` values.
+#[wasm_bindgen]
+pub fn get_key_bindings(for_rom: bool) -> String {
+    key_bindings_layer(for_rom).with(|bindings| bindings.borrow().to_string())
+}
+
+#[wasm_bindgen]
+pub fn set_key_bindings(bindings: &str, for_rom: bool) -> Result<(), JsValue> {
+    let bindings: KeyBindings = bindings
+        .parse()
+        .map_err(|_| JsValue::from(format!("Invalid key bindings: {}", bindings)))?;
+
+    update_key_bindings(for_rom, |current| *current = bindings)
+}
+
+// Back to QWERTY for every ROM, or no overrides for the running ROM
+#[wasm_bindgen]
+pub fn reset_key_bindings(for_rom: bool) -> Result<(), JsValue> {
+    update_key_bindings(for_rom, |current| {
+        *current = if for_rom {
+            KeyBindings::default()
+        } else {
+            KeyBindings::qwerty()
+        }
+    })
+}
+
+// Physical keys that currently press `key`
+#[wasm_bindgen]
+pub fn key_binding_codes(key: u8) -> js_sys::Array {
+    KEYS.with(|keys| {
+        keys.borrow()
+            .codes_for(key)
+            .into_iter()
+            .map(JsValue::from)
+            .collect()
+    })
+}
+
+// Binds the next key pressed to `key`, then calls `done` with its code
+#[wasm_bindgen]
+pub fn capture_key_binding(key: u8, for_rom: bool, done: js_sys::Function) -> Result<(), JsValue> {
+    if key > 0xF {
+        return Err(JsValue::from(format!("Invalid key: {}", key)));
+    }
+
+    with_emulator(|emulator| {
+        emulator.keyboard().capture_next_key(move |code| {
+            if let Err(e) = update_key_bindings(for_rom, |bindings| bindings.bind(&code, Some(key)))
+            {
+                console::warn_1(&e);
+            }
+            if let Err(e) = done.call1(&JsValue::NULL, &JsValue::from(code)) {
+                console::warn_1(&e);
+            }
+        });
+
+        Ok(())
+    })
+}
+
+// Emulator hotkeys as a comma separated list of hotkey=code, e.g.
+// "pause=KeyP,reset=Backspace", see `Hotkey` for the names
+#[wasm_bindgen]
+pub fn get_hotkeys() -> String {
+    HOTKEYS.with(|hotkeys| hotkeys.borrow().to_string())
+}
+
+#[wasm_bindgen]
+pub fn hotkey_names() -> js_sys::Array {
+    Hotkey::names().map(JsValue::from).collect()
+}
+
+#[wasm_bindgen]
+pub fn set_hotkeys(hotkeys: &str) -> Result<(), JsValue> {
+    let hotkeys: Hotkeys = hotkeys
+        .parse()
+        .map_err(|_| JsValue::from(format!("Invalid hotkeys: {}", hotkeys)))?;
+
+    update_hotkeys(|current| *current = hotkeys)
+}
+
+// Binds the next key pressed to a hotkey, then calls `done` with its code
+#[wasm_bindgen]
+pub fn capture_hotkey(hotkey: &str, done: js_sys::Function) -> Result<(), JsValue> {
+    let hotkey: Hotkey = hotkey
+        .parse()
+        .map_err(|_| JsValue::from(format!("Unknown hotkey: {}", hotkey)))?;
+
+    with_emulator(|emulator| {
+        emulator.keyboard().capture_next_key(move |code| {
+            if let Err(e) = update_hotkeys(|hotkeys| hotkeys.bind(&code, hotkey)) {
+                console::warn_1(&e);
+            }
+            if let Err(e) = done.call1(&JsValue::NULL, &JsValue::from(code)) {
+                console::warn_1(&e);
+            }
+        });
+
+        Ok(())
+    })
+}
+
+fn update_hotkeys(f: impl FnOnce(&mut Hotkeys)) -> Result<(), JsValue> {
+    let hotkeys = HOTKEYS.with(|hotkeys| {
+        let mut hotkeys = hotkeys.borrow_mut();
+        f(&mut hotkeys);
+        hotkeys.to_string()
+    });
+
+    if let Some(storage) = local_storage() {
+        storage.set_item(HOTKEYS_STORAGE_KEY, &hotkeys)?;
+    }
+
+    Ok(())
+}
+
+fn key_bindings_layer(for_rom: bool) -> &'static std::thread::LocalKey<RefCell<KeyBindings>> {
+    if for_rom {
+        &ROM_KEY_BINDINGS
+    } else {
+        &KEY_BINDINGS
+    }
+}
+
+fn key_bindings_storage_key(for_rom: bool) -> String {
+    if for_rom {
+        format!("{}/{}", KEYS_STORAGE_KEY, get_rom_name())
+    } else {
+        KEYS_STORAGE_KEY.to_string()
+    }
+}
+
+// Change one layer of key bindings, save it and update the running emulator
+fn update_key_bindings(for_rom: bool, f: impl FnOnce(&mut KeyBindings)) -> Result<(), JsValue> {
+    let bindings = key_bindings_layer(for_rom).with(|bindings| {
+        let mut bindings = bindings.borrow_mut();
+        f(&mut bindings);
+        bindings.to_string()
+    });
+
+    KEYS.with(|keys| keys.replace(merged_key_bindings()));
+
+    if let Some(storage) = local_storage() {
+        storage.set_item(&key_bindings_storage_key(for_rom), &bindings)?;
+    }
+
+    Ok(())
+}
+
+fn merged_key_bindings() -> KeyBindings {
+    let overrides = ROM_KEY_BINDINGS.with(|bindings| bindings.borrow().clone());
+    KEY_BINDINGS.with(|bindings| bindings.borrow().with_overrides(&overrides))
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn load_palette() -> Palette {
+    local_storage()
+        .and_then(|storage| storage.get_item(PALETTE_STORAGE_KEY).ok()?)
+        .and_then(|palette| palette.parse().ok())
+        .unwrap_or_default()
+}
+
+fn load_gamepad_bindings() -> GamepadBindings {
+    let key = format!("{}{}", GAMEPAD_STORAGE_PREFIX, get_rom_name());
+
+    local_storage()
+        .and_then(|storage| storage.get_item(&key).ok()?)
+        .and_then(|bindings| bindings.parse().ok())
+        .unwrap_or_default()
+}
+
+fn load_hotkeys() -> Hotkeys {
+    local_storage()
+        .and_then(|storage| storage.get_item(HOTKEYS_STORAGE_KEY).ok()?)
+        .and_then(|hotkeys| hotkeys.parse().ok())
+        .unwrap_or_default()
+}
+
+fn load_key_bindings(for_rom: bool) -> KeyBindings {
+    let saved = local_storage()
+        .and_then(|storage| storage.get_item(&key_bindings_storage_key(for_rom)).ok()?)
+        .and_then(|bindings| bindings.parse().ok());
+
+    match saved {
+        Some(bindings) => bindings,
+        None if for_rom => KeyBindings::default(),
+        None => KeyBindings::qwerty(),
+    }
+}
+
+fn get_query() -> HashMap<String, String> {
     let href = web_sys::window().unwrap().location().href().unwrap();
     let parsed_url = Url::parse(&href).expect("could not parse url");
 
-    let query: HashMap<String, String> = parsed_url.query_pairs().into_owned().collect();
+    parsed_url.query_pairs().into_owned().collect()
+}
+
+fn get_quirks() -> Result<Option<Quirks>, JsValue> {
+    // quirks are turned on from the url query, e.g. "?display_wait=1" or
+    // "?quirks=display_wait,shift_vy", and "?quirks=" turns them all off.
+    // Without either, or with "?quirks=auto", they're guessed from the ROM.
+    let query = get_query();
+
+    let names: Vec<&str> = Quirks::names()
+        .filter(|name| query.contains_key(*name))
+        .collect();
+    let parse = |quirks: &str| {
+        quirks
+            .parse()
+            .map(Some)
+            .map_err(|_| JsValue::from(format!("Invalid quirks: {}", quirks)))
+    };
+
+    match query.get("quirks").map(String::as_str) {
+        Some("auto") => Ok(None),
+        Some(quirks) => parse(quirks),
+        None if names.is_empty() => Ok(None),
+        None => parse(&names.join(",")),
+    }
+}
+
+fn get_persistence() -> Persistence {
+    // e.g. "?persistence=decay", see `Persistence` for the options
+    get_query()
+        .get("persistence")
+        .and_then(|mode| mode.parse().ok())
+        .unwrap_or(Persistence::Off)
+}
+
+// The bundled ROM picked in the url
+fn page_rom() -> Vec<u8> {
+    Asset::get(&format!("{}.ch8", get_rom_name()))
+        .expect("Could not get ROM")
+        .into_owned()
+}
+
+fn get_rom_name() -> String {
+    // get rom name from url query defaulting to "test_opcode"
+    let query = get_query();
 
     let rom_name = query
         .get("rom")
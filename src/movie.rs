@@ -0,0 +1,331 @@
+// Input movies: a record of the keypad on every frame of a run, along with
+// everything else needed to repeat it exactly (the ROM, the random seed and
+// the quirks). Timers and random numbers only depend on these, so playing a
+// movie back through the same ROM reproduces the original run frame for frame.
+//
+// Movies are saved as text, one setting or keypad change per line:
+//
+//   chip8-movie 1
+//   rom 9c3ab4e2d1f07a58
+//   seed 1234
+//   quirks display_wait
+//   frames 600
+//   key 12 5
+//   key 30:4 -
+//
+// "key <frame> <key>" means the key was pressed from that frame on, "-" is
+// no key. "<frame>:<step>" is a change part way through a frame, before the
+// given instruction of the frame. Frames and steps are counted from 0.
+// Every frame runs `STEPS_PER_FRAME` instructions.
+
+use crate::chip8::{Chip8Error, Quirks, STEPS_PER_FRAME};
+use crate::headless::Headless;
+
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "chip8-movie 1";
+
+#[derive(Debug)]
+pub enum MovieError {
+    Syntax(usize),
+    UnsupportedVersion,
+    RomMismatch,
+    // Asked for something that can't be recorded, while recording
+    Recording,
+    Emulator(Chip8Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Syntax(line) => write!(f, "Invalid movie file at line {}", line),
+            MovieError::UnsupportedVersion => write!(f, "Not a supported movie file"),
+            MovieError::RomMismatch => write!(f, "Movie was recorded with a different ROM"),
+            MovieError::Recording => write!(f, "Not while recording a movie"),
+            MovieError::Emulator(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<Chip8Error> for MovieError {
+    fn from(e: Chip8Error) -> Self {
+        MovieError::Emulator(e)
+    }
+}
+
+// FNV-1a, enough to tell ROMs apart without pulling in a crypto hash
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+
+    // Keypad changes as ((frame, step), key), in order
+    changes: Vec<((u64, usize), Option<u8>)>,
+
+    // Number of frames recorded
+    frames: u64,
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], seed: u64, quirks: Quirks) -> Movie {
+        Movie {
+            rom_hash: rom_hash(rom),
+            seed,
+            quirks,
+            changes: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    // Record the keys held down during each instruction of the next frame,
+    // call once per frame
+    pub fn record(&mut self, keys: &[Option<u8>]) {
+        for (step, key) in keys.iter().enumerate() {
+            if self.key_at(self.frames, step) != *key {
+                self.changes.push(((self.frames, step), *key));
+            }
+        }
+
+        self.frames += 1;
+    }
+
+    // The key held down during an instruction of a frame
+    pub fn key_at(&self, frame: u64, step: usize) -> Option<u8> {
+        // Last change on or before the instruction
+        let after = self.changes.partition_point(|(at, _)| *at <= (frame, step));
+
+        match after {
+            0 => None,
+            _ => self.changes[after - 1].1,
+        }
+    }
+
+    // The key held down during each instruction of a frame
+    pub fn frame_keys(&self, frame: u64, steps: usize) -> Vec<Option<u8>> {
+        (0..steps).map(|step| self.key_at(frame, step)).collect()
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_hash == rom_hash(rom)
+    }
+}
+
+// Play a movie back from power on, the returned machine is in the state the
+// recording finished in
+pub fn replay(rom: &[u8], movie: &Movie) -> Result<Headless, MovieError> {
+    if !movie.matches_rom(rom) {
+        return Err(MovieError::RomMismatch);
+    }
+
+    let mut headless = Headless::new(rom, movie.quirks)?;
+    headless.chip8_mut().set_seed(movie.seed);
+
+    while headless.frame() < movie.frames() {
+        headless.run_frame_with_keys(&movie.frame_keys(headless.frame(), STEPS_PER_FRAME))?;
+    }
+
+    Ok(headless)
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "frames {}", self.frames)?;
+
+        for ((frame, step), key) in self.changes.iter() {
+            write!(f, "key {}", frame)?;
+            if *step != 0 {
+                write!(f, ":{}", step)?;
+            }
+            match key {
+                Some(key) => writeln!(f, " {:X}", key)?,
+                None => writeln!(f, " -")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_key(key: &str) -> Result<Option<u8>, ()> {
+    match key {
+        "-" => Ok(None),
+        _ => match u8::from_str_radix(key, 16) {
+            Ok(key) if key <= 0xF => Ok(Some(key)),
+            _ => Err(()),
+        },
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => {}
+            _ => return Err(MovieError::UnsupportedVersion),
+        }
+
+        let mut movie = Movie {
+            rom_hash: 0,
+            seed: 0,
+            quirks: Quirks::default(),
+            changes: Vec::new(),
+            frames: 0,
+        };
+
+        for (number, line) in lines {
+            let syntax = || MovieError::Syntax(number + 1);
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+
+            match (name, &args[..]) {
+                ("rom", [hash]) => {
+                    movie.rom_hash = u64::from_str_radix(hash, 16).map_err(|_| syntax())?
+                }
+                ("seed", [seed]) => movie.seed = seed.parse().map_err(|_| syntax())?,
+                ("quirks", []) => movie.quirks = Quirks::default(),
+                ("quirks", [quirks]) => movie.quirks = quirks.parse().map_err(|_| syntax())?,
+                ("frames", [frames]) => movie.frames = frames.parse().map_err(|_| syntax())?,
+                ("key", [at, key]) => {
+                    let (frame, step) = at.split_once(':').unwrap_or((at, "0"));
+                    let at: (u64, usize) = (
+                        frame.parse().map_err(|_| syntax())?,
+                        step.parse().map_err(|_| syntax())?,
+                    );
+                    let key = parse_key(key).map_err(|_| syntax())?;
+
+                    // Changes have to be in order for lookups to work
+                    if let Some((last, _)) = movie.changes.last() {
+                        if at <= *last {
+                            return Err(syntax());
+                        }
+                    }
+                    movie.changes.push((at, key));
+                }
+                _ => return Err(syntax()),
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let mut movie = Movie::new(&[0x00, 0xE0], 7, Quirks::default());
+        for key in [None, None, Some(5), Some(5), None, Some(0)] {
+            movie.record(&[key, key]);
+        }
+        movie.record(&[Some(0), Some(3)]);
+
+        assert_eq!(movie.frames(), 7);
+        assert_eq!(
+            movie.changes,
+            [
+                ((2, 0), Some(5)),
+                ((4, 0), None),
+                ((5, 0), Some(0)),
+                ((6, 1), Some(3))
+            ]
+        );
+        assert_eq!(movie.key_at(0, 0), None);
+        assert_eq!(movie.key_at(3, 1), Some(5));
+        assert_eq!(movie.key_at(4, 0), None);
+        assert_eq!(movie.frame_keys(6, 3), [Some(0), Some(3), Some(3)]);
+        assert_eq!(movie.key_at(100, 0), Some(3));
+    }
+
+    #[test]
+    fn test_movie_round_trip() {
+        let mut movie = Movie::new(
+            &[0x12, 0x00],
+            u64::MAX,
+            Quirks {
+                display_wait: true,
+                ..Quirks::default()
+            },
+        );
+        movie.record(&[Some(0xA)]);
+        movie.record(&[Some(0xA), None]);
+
+        let text = movie.to_string();
+        assert!(text.contains("key 0 A\nkey 1:1 -\n"));
+        assert_eq!(text.parse::<Movie>().unwrap(), movie);
+
+        assert!(matches!(
+            "chip8-movie 2\n".parse::<Movie>(),
+            Err(MovieError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            "chip8-movie 1\nkey 3 G\n".parse::<Movie>(),
+            Err(MovieError::Syntax(2))
+        ));
+        assert!(matches!(
+            "chip8-movie 1\nkey 3:2 1\nkey 3:1 2\n".parse::<Movie>(),
+            Err(MovieError::Syntax(3))
+        ));
+    }
+
+    #[test]
+    fn test_replay_is_exact() {
+        // Fill V0-VF with random numbers, wait for a key, then start over
+        let rom = [
+            0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF, 0xC4, 0xFF, 0xC5, 0xFF, 0xF6, 0x0A,
+            0x12, 0x00,
+        ];
+
+        let mut original = Headless::new(&rom, Quirks::default()).unwrap();
+        let mut movie = Movie::new(&rom, original.chip8().seed(), Quirks::default());
+        for frame in 0..30 {
+            // Keys change part way through some frames
+            let keys: Vec<Option<u8>> = (0..STEPS_PER_FRAME)
+                .map(|step| match (frame + step) % 7 {
+                    3 => Some(frame as u8 % 16),
+                    _ => None,
+                })
+                .collect();
+            movie.record(&keys);
+            original.run_frame_with_keys(&keys).unwrap();
+        }
+
+        let replayed = replay(&rom, &movie.to_string().parse().unwrap()).unwrap();
+        assert_eq!(replayed.frame(), 30);
+        assert_eq!(replayed.chip8().registers(), original.chip8().registers());
+        assert_eq!(
+            replayed.chip8().program_counter(),
+            original.chip8().program_counter()
+        );
+
+        assert!(matches!(
+            replay(&[0x12, 0x00], &movie),
+            Err(MovieError::RomMismatch)
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+// Minimal PNG encoder for screenshots of the Chip-8 screen.
+//
+// The screen only ever has two colours, so images are written as 1 bit
+// indexed colour. That keeps files small enough that the image data can
+// be stored without compression, which means no deflate implementation.
+// https://www.w3.org/TR/PNG/
+
+use crate::screen::{Palette, RawGrid, MAX_SCALE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const BIT_DEPTH: u8 = 1;
+const COLOR_TYPE_INDEXED: u8 = 3;
+
+// Largest amount of data a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+// Encode the screen as a PNG, each chip-8 pixel becomes a `scale` x `scale`
+// square. `scale` has to be from 1 to `MAX_SCALE`.
+pub fn encode_grid(grid: &RawGrid, scale: usize, palette: &Palette) -> Result<Vec<u8>, String> {
+    if !(1..=MAX_SCALE).contains(&scale) {
+        return Err(format!(
+            "Scale must be from 1 to {}, not {}",
+            MAX_SCALE, scale
+        ));
+    }
+    let width = SCREEN_WIDTH * scale;
+    let height = SCREEN_HEIGHT * scale;
+
+    let mut png = Vec::new();
+    png.extend_from_slice(&SIGNATURE);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[BIT_DEPTH, COLOR_TYPE_INDEXED, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Index 0 is the background, 1 the foreground
+    let (bg, fg) = (palette.background(), palette.foreground());
+    write_chunk(
+        &mut png,
+        b"PLTE",
+        &[bg[0], bg[1], bg[2], fg[0], fg[1], fg[2]],
+    );
+    write_chunk(&mut png, b"tRNS", &[bg[3], fg[3]]);
+
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines(grid, scale)));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    Ok(png)
+}
+
+// Image data before compression, every row starts with its filter type
+fn scanlines(grid: &RawGrid, scale: usize) -> Vec<u8> {
+    let row_bytes = (SCREEN_WIDTH * scale).div_ceil(8);
+    let mut data = Vec::with_capacity((row_bytes + 1) * SCREEN_HEIGHT * scale);
+
+    for scanline in grid.iter() {
+        let mut row = vec![0u8; row_bytes];
+        for x in 0..SCREEN_WIDTH * scale {
+            // Bit 0 of the scanline is the leftmost pixel
+            if (scanline >> (x / scale)) & 0x01 == 1 {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+
+        for _ in 0..scale {
+            // Filter type 0, none
+            data.push(0);
+            data.extend_from_slice(&row);
+        }
+    }
+
+    data
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wrap data in a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // CMF/FLG: deflate with a 32K window, no dictionary, fastest compression
+    let mut zlib = vec![0x78, 0x01];
+
+    if data.is_empty() {
+        push_stored_block(&mut zlib, &[], true);
+    }
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        push_stored_block(&mut zlib, block, is_final);
+    }
+
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+
+    zlib
+}
+
+fn push_stored_block(zlib: &mut Vec<u8>, block: &[u8], is_final: bool) {
+    // BFINAL bit then BTYPE 00 (stored)
+    zlib.push(is_final as u8);
+
+    let len = block.len() as u16;
+    zlib.extend_from_slice(&len.to_le_bytes());
+    zlib.extend_from_slice(&(!len).to_le_bytes());
+    zlib.extend_from_slice(block);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_zlib_stored() {
+        let data = vec![0xAB; MAX_STORED_BLOCK + 10];
+        let zlib = zlib_stored(&data);
+
+        // header, two blocks with 5 byte headers, checksum
+        assert_eq!(zlib.len(), 2 + 5 + MAX_STORED_BLOCK + 5 + 10 + 4);
+        assert_eq!(zlib[2], 0);
+        assert_eq!(zlib[2 + 5 + MAX_STORED_BLOCK], 1);
+    }
+
+    #[test]
+    fn test_encode_grid() {
+        let mut grid: RawGrid = [0; SCREEN_HEIGHT];
+        grid[0] = 0b1;
+        grid[31] = 1 << 63;
+
+        let png = encode_grid(&grid, 2, &Palette::default()).unwrap();
+        assert_eq!(png[..8], SIGNATURE);
+
+        // IHDR comes first, 128x64
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], 128u32.to_be_bytes());
+        assert_eq!(png[20..24], 64u32.to_be_bytes());
+
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+
+        let rows = scanlines(&grid, 2);
+        let row_len = 1 + 128 / 8;
+        assert_eq!(rows.len(), row_len * 64);
+
+        // Top left pixel is doubled in both directions
+        assert_eq!(rows[1], 0b1100_0000);
+        assert_eq!(rows[row_len + 1], 0b1100_0000);
+        assert_eq!(rows[2 * row_len + 1], 0);
+
+        // Bottom right
+        assert_eq!(rows[rows.len() - 1], 0b0000_0011);
+
+        assert!(encode_grid(&grid, 0, &Palette::default()).is_err());
+        assert!(encode_grid(&grid, usize::MAX, &Palette::default()).is_err());
+    }
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    #[test]
+    fn test_chunks() {
+        // Top left and bottom right pixels at 1:1, 64x32
+        let mut grid: RawGrid = [0; SCREEN_HEIGHT];
+        grid[0] = 0b1;
+        grid[31] = 1 << 63;
+        let palette: Palette = "#000000,#FFFFFF".parse().unwrap();
+        let png = encode_grid(&grid, 1, &palette).unwrap();
+
+        // (type, data, CRC) of every chunk
+        let mut chunks = Vec::new();
+        let mut at = SIGNATURE.len();
+        while at < png.len() {
+            let len = be_u32(&png[at..]) as usize;
+            let data = &png[at + 8..at + 8 + len];
+            chunks.push((&png[at + 4..at + 8], data, be_u32(&png[at + 8 + len..])));
+            at += 12 + len;
+        }
+        assert_eq!(at, png.len());
+
+        let kinds: Vec<&[u8]> = chunks.iter().map(|chunk| chunk.0).collect();
+        assert_eq!(kinds, [b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND"]);
+        let crcs: Vec<u32> = chunks.iter().map(|chunk| chunk.2).collect();
+        assert_eq!(
+            crcs,
+            [
+                0x9853_ECC7,
+                0xA5D9_9FDD,
+                0xC8B5_DFC7,
+                0xBE33_9B77,
+                0xAE42_6082
+            ]
+        );
+
+        // One final stored block of 32 rows, each a filter byte and 8 bytes
+        let idat = chunks[3].1;
+        assert_eq!(idat.len(), 2 + 5 + 288 + 4);
+        assert_eq!(idat[..7], [0x78, 0x01, 0x01, 0x20, 0x01, 0xDF, 0xFE]);
+
+        let rows = &idat[7..7 + 288];
+        assert!(rows.chunks(9).all(|row| row[0] == 0));
+        assert_eq!(rows[1], 0x80);
+        assert_eq!(rows[287], 0x01);
+        assert_eq!(rows.iter().filter(|byte| **byte != 0).count(), 2);
+
+        assert_eq!(be_u32(&idat[7 + 288..]), 0x90A1_0082);
+    }
+}
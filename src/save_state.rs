@@ -0,0 +1,223 @@
+// Save states: a snapshot of the machine part way through a run, along
+// with the ROM it belongs to and the settings it was running with.
+//
+// Save states are saved as text so they can be kept in browser storage and
+// passed around as files:
+//
+//   chip8-state 1
+//   rom 9c3ab4e2d1f07a58
+//   frame 600
+//   time 1760000000000
+//   quirks display_wait
+//   snapshot 00e0a2...
+//
+// "time" is when the state was saved in milliseconds since the unix epoch
+// and "snapshot" is `Snapshot::to_bytes` in hex, see `SaveState::to_text`.
+
+use crate::chip8::{Quirks, Snapshot};
+use crate::movie::rom_hash;
+use crate::screen::RawGrid;
+
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "chip8-state 1";
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Syntax(usize),
+    UnsupportedVersion,
+    RomMismatch,
+    // The snapshot can't be written, see `Snapshot::to_bytes`
+    Unwritable,
+    // Loading a state would leave a movie being recorded unable to repeat
+    // the run
+    RecordingMovie,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Syntax(line) => write!(f, "Invalid save state at line {}", line),
+            SaveStateError::UnsupportedVersion => write!(f, "Not a supported save state"),
+            SaveStateError::RomMismatch => {
+                write!(f, "Save state was made with a different ROM")
+            }
+            SaveStateError::Unwritable => write!(f, "State can't be saved"),
+            SaveStateError::RecordingMovie => {
+                write!(f, "Stop recording the movie to load a state")
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SaveState {
+    pub rom_hash: u64,
+    // Frames run since power on
+    pub frame: u64,
+    pub time: u64,
+    pub quirks: Quirks,
+    pub snapshot: Snapshot,
+}
+
+impl SaveState {
+    pub fn new(rom: &[u8], frame: u64, time: u64, quirks: Quirks, snapshot: Snapshot) -> SaveState {
+        SaveState {
+            rom_hash: rom_hash(rom),
+            frame,
+            time,
+            quirks,
+            snapshot,
+        }
+    }
+
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_hash == rom_hash(rom)
+    }
+
+    // What was on screen, for thumbnails
+    pub fn screen(&self) -> &RawGrid {
+        self.snapshot.screen()
+    }
+
+    // The text format above. Not `Display` since a snapshot can fail to
+    // be written.
+    pub fn to_text(&self) -> Result<String, SaveStateError> {
+        let bytes = self.snapshot.to_bytes().ok_or(SaveStateError::Unwritable)?;
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        Ok(format!(
+            "{}\nrom {:016x}\nframe {}\ntime {}\nquirks {}\nsnapshot {}\n",
+            HEADER, self.rom_hash, self.frame, self.time, self.quirks, hex
+        ))
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl FromStr for SaveState {
+    type Err = SaveStateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        match lines.next() {
+            Some((_, header)) if header.trim() == HEADER => {}
+            _ => return Err(SaveStateError::UnsupportedVersion),
+        }
+
+        let mut rom_hash = 0;
+        let mut frame = 0;
+        let mut time = 0;
+        let mut quirks = Quirks::default();
+        let mut snapshot = None;
+
+        for (number, line) in lines {
+            let syntax = || SaveStateError::Syntax(number + 1);
+
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or("");
+            let args: Vec<&str> = words.collect();
+
+            match (name, &args[..]) {
+                ("rom", [hash]) => {
+                    rom_hash = u64::from_str_radix(hash, 16).map_err(|_| syntax())?
+                }
+                ("frame", [value]) => frame = value.parse().map_err(|_| syntax())?,
+                ("time", [value]) => time = value.parse().map_err(|_| syntax())?,
+                ("quirks", []) => quirks = Quirks::default(),
+                ("quirks", [value]) => quirks = value.parse().map_err(|_| syntax())?,
+                ("snapshot", [hex]) => {
+                    let bytes = parse_hex(hex).ok_or_else(syntax)?;
+                    snapshot = Some(Snapshot::from_bytes(&bytes).ok_or_else(syntax)?);
+                }
+                _ => return Err(syntax()),
+            }
+        }
+
+        // A state without a snapshot can't be loaded
+        let snapshot = snapshot.ok_or(SaveStateError::Syntax(s.lines().count()))?;
+
+        Ok(SaveState {
+            rom_hash,
+            frame,
+            time,
+            quirks,
+            snapshot,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::STEPS_PER_FRAME;
+    use crate::headless::Headless;
+
+    // Draws random digits in random places, forever
+    const ROM: [u8; 12] = [
+        0xC0, 0x0F, 0xC1, 0x3F, 0xC2, 0x1F, 0xF0, 0x29, 0xD1, 0x25, 0x12, 0x00,
+    ];
+
+    #[test]
+    fn test_save_and_load() {
+        let mut original = Headless::new(&ROM, Quirks::default()).unwrap();
+        original.chip8_mut().set_seed(99);
+        for _ in 0..10 {
+            original.run_frame(STEPS_PER_FRAME).unwrap();
+        }
+
+        let saved = SaveState::new(
+            &ROM,
+            original.frame(),
+            1234,
+            Quirks::default(),
+            original.chip8().snapshot(),
+        );
+        let text = saved.to_text().unwrap();
+        let loaded: SaveState = text.parse().unwrap();
+        assert_eq!(loaded.frame, 10);
+        assert_eq!(loaded.time, 1234);
+        assert!(loaded.matches_rom(&ROM));
+        assert_eq!(loaded.screen(), original.chip8().screen());
+
+        // Both carry on the same way, random numbers included
+        let mut restored = Headless::new(&ROM, Quirks::default()).unwrap();
+        restored.chip8_mut().restore(&loaded.snapshot);
+        for _ in 0..10 {
+            original.run_frame(STEPS_PER_FRAME).unwrap();
+            restored.run_frame(STEPS_PER_FRAME).unwrap();
+        }
+        assert_eq!(restored.chip8().screen(), original.chip8().screen());
+        assert_eq!(restored.chip8().registers(), original.chip8().registers());
+    }
+
+    #[test]
+    fn test_invalid_save_states() {
+        assert!(matches!(
+            "chip8-movie 1\n".parse::<SaveState>(),
+            Err(SaveStateError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            "chip8-state 1\nframe 1\n".parse::<SaveState>(),
+            Err(SaveStateError::Syntax(_))
+        ));
+        assert!(matches!(
+            "chip8-state 1\nsnapshot 00ff\n".parse::<SaveState>(),
+            Err(SaveStateError::Syntax(2))
+        ));
+    }
+}
@@ -1,11 +1,66 @@
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
+use super::palette::Palette;
 
-use web_sys::CanvasRenderingContext2d;
+use wasm_bindgen::{Clamped, JsCast};
+
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::str::FromStr;
 
 // 32 rows of 64 bits, 1 bit = 1 pixel
 pub type RawGrid = [u64; 32];
 
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+// Largest number of image pixels per chip-8 pixel in exported images,
+// already 4096x2048
+pub const MAX_SCALE: usize = 64;
+
+// Each pixel in the image buffer is 4 bytes, RGBA
+const BYTES_PER_PIXEL: usize = 4;
+const ROW_BYTES: usize = SCREEN_WIDTH * BYTES_PER_PIXEL;
+
+// Frames held by `Persistence::Hold` when no count is given
+const DEFAULT_HOLD_FRAMES: u8 = 3;
+
+// XOR drawing means games erase and redraw sprites every frame, which
+// flickers badly on a modern display. Persistence keeps pixels visible
+// for a little while after they are turned off to hide that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persistence {
+    // Show exactly what is in the framebuffer
+    Off,
+
+    // Pixels fade out over a few frames, like a CRT phosphor
+    Decay,
+
+    // Show the current and previous frame OR'd together
+    Blend,
+
+    // Pixels stay fully lit for N frames after being turned off
+    Hold(u8),
+}
+
+impl FromStr for Persistence {
+    type Err = ();
+
+    // Parses "off", "decay", "blend", "hold" or "hold-N"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Persistence::Off),
+            "decay" => Ok(Persistence::Decay),
+            "blend" => Ok(Persistence::Blend),
+            "hold" => Ok(Persistence::Hold(DEFAULT_HOLD_FRAMES)),
+            _ => match s.strip_prefix("hold-") {
+                Some(frames) => frames.parse().map(Persistence::Hold).map_err(|_| ()),
+                None => Err(()),
+            },
+        }
+    }
+}
+
 // Canvas will encapsulate all operations with the canvas on the webpage
 // Originally this was implemented in JS, but I had trouble with numbers
 // in JS and moving the raw screen data into JS. Ultimatly it was easier
@@ -13,26 +68,35 @@ pub type RawGrid = [u64; 32];
 // the class live in JS might work better in the future.
 // The JS version still lives in code pen, if curious:
 // https://codepen.io/edison-moreland/pen/PowKeLv
+//
+// The canvas element is exactly 64x32, one canvas pixel per chip-8 pixel,
+// and is scaled up with CSS. Rendering happens in an RGBA buffer on the Rust
+// side which is handed to the canvas with a single `putImageData` per frame.
 pub struct Canvas {
-    scale_x: f64,
-    scale_y: f64,
     ctx: CanvasRenderingContext2d,
+
+    pixels: Vec<u8>,
+
+    // What the buffer currently holds, used to find rows that changed
+    last_grid: RawGrid,
+    force_redraw: bool,
+
+    persistence: Persistence,
+
+    // Shared so the palette can be changed while the emulator is running
+    palette: Rc<Cell<Palette>>,
+    drawn_palette: Palette,
+
+    // Frames since each pixel was last lit, saturating at u8::MAX
+    ages: Vec<u8>,
+    previous_grid: RawGrid,
 }
 
 impl Canvas {
-    pub fn new(canvas_id: &str) -> Canvas {
-        // Some hot garbage to get the canvas element
-        let document = web_sys::window().unwrap().document().unwrap();
-        let canvas = document.get_element_by_id(canvas_id).unwrap();
-        let canvas: web_sys::HtmlCanvasElement = canvas
-            .dyn_into::<web_sys::HtmlCanvasElement>()
-            .map_err(|_| ())
-            .unwrap();
-
-        // Adjust drawing to canvas size
+    pub fn new(canvas: &web_sys::HtmlCanvasElement, palette: Rc<Cell<Palette>>) -> Canvas {
         // Only supported video mode is 64x32 (for now)
-        let scale_x = canvas.width() / 64;
-        let scale_y = canvas.height() / 32;
+        canvas.set_width(SCREEN_WIDTH as u32);
+        canvas.set_height(SCREEN_HEIGHT as u32);
 
         // More hot garbage to get a context
         let context = canvas
@@ -42,44 +106,156 @@ impl Canvas {
             .dyn_into::<web_sys::CanvasRenderingContext2d>()
             .unwrap();
 
-        context.set_fill_style(&JsValue::from_str("rgb(0, 0, 0)"));
-        context.set_global_composite_operation("overlay").expect("Failed to set composite operation, this shouldn't happen");
+        // Everything is redrawn with the palette on the first frame
+        let pixels = vec![0; ROW_BYTES * SCREEN_HEIGHT];
 
-        return Canvas {
-            scale_x: scale_x as f64,
-            scale_y: scale_y as f64,
+        Canvas {
             ctx: context,
-        };
+            pixels,
+            last_grid: [0; SCREEN_HEIGHT],
+            force_redraw: true,
+
+            persistence: Persistence::Off,
+
+            drawn_palette: palette.get(),
+            palette,
+
+            ages: vec![u8::MAX; SCREEN_WIDTH * SCREEN_HEIGHT],
+            previous_grid: [0; SCREEN_HEIGHT],
+        }
     }
 
-    fn clear_screen(&self) {
-        self.ctx
-            .clear_rect(0.0, 0.0, 64.0 * self.scale_x, 32.0 * self.scale_y);
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.persistence = persistence;
+
+        // The buffer may hold faded pixels the new mode doesn't know about
+        self.force_redraw = true;
     }
 
-    fn draw_pixel(&self, x: usize, y: usize) {
-        self.ctx.fill_rect(
-            (x as f64) * self.scale_x,
-            (y as f64) * self.scale_y,
-            self.scale_x,
-            self.scale_y,
-        )
+    fn age_pixels(&mut self, screen: &RawGrid) {
+        for (y, scanline) in screen.iter().enumerate() {
+            let ages = &mut self.ages[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+
+            for (x, age) in ages.iter_mut().enumerate() {
+                if (scanline >> x) & 0x01 == 1 {
+                    *age = 0;
+                } else {
+                    *age = age.saturating_add(1);
+                }
+            }
+        }
     }
 
-    pub fn draw_grid(&self, screen: &RawGrid) {
-        self.clear_screen();
+    // Which pixels should be fully lit on this row
+    fn visible_row(&self, y: usize, screen: &RawGrid) -> u64 {
+        match self.persistence {
+            Persistence::Off | Persistence::Decay => screen[y],
+            Persistence::Blend => screen[y] | self.previous_grid[y],
+            Persistence::Hold(frames) => {
+                let ages = &self.ages[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
 
-        for (y, scanline) in screen.iter().enumerate() {
-            let mut pixel_data = *scanline;
+                ages.iter()
+                    .enumerate()
+                    .filter(|(_, age)| **age <= frames)
+                    .fold(0, |scanline, (x, _)| scanline | (1 << x))
+            }
+        }
+    }
+
+    // Returns true if any pixel on the row changed
+    fn write_faded_row(&mut self, y: usize) -> bool {
+        let ages = &self.ages[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+        let row = &mut self.pixels[y * ROW_BYTES..(y + 1) * ROW_BYTES];
+
+        let mut changed = false;
+        for (pixel, age) in row.chunks_mut(BYTES_PER_PIXEL).zip(ages) {
+            // Brightness halves every frame after the pixel is turned off
+            let intensity = u8::MAX.checked_shr(*age as u32).unwrap_or(0);
+            let color = self.drawn_palette.fade(intensity);
+
+            if self.force_redraw || pixel != color {
+                pixel.copy_from_slice(&color);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn write_row(&mut self, y: usize, scanline: u64) {
+        let row = &mut self.pixels[y * ROW_BYTES..(y + 1) * ROW_BYTES];
 
-            for x in 0..64 {
-                let is_pixel_set = pixel_data & 0x01;
-                pixel_data >>= 1;
+        let (on, off) = (
+            self.drawn_palette.foreground(),
+            self.drawn_palette.background(),
+        );
+        for (x, pixel) in row.chunks_mut(BYTES_PER_PIXEL).enumerate() {
+            let is_pixel_set = (scanline >> x) & 0x01 == 1;
 
-                if is_pixel_set == 1 {
-                    self.draw_pixel(x, y);
+            pixel.copy_from_slice(if is_pixel_set { &on } else { &off });
+        }
+    }
+
+    pub fn draw_grid(&mut self, screen: &RawGrid) {
+        self.age_pixels(screen);
+
+        let palette = self.palette.get();
+        if palette != self.drawn_palette {
+            self.drawn_palette = palette;
+            self.force_redraw = true;
+        }
+
+        // Only rows that changed since the last frame are rewritten,
+        // and only the band of rows between them is sent to the canvas
+        let mut dirty: Option<(usize, usize)> = None;
+
+        for y in 0..SCREEN_HEIGHT {
+            let changed = if self.persistence == Persistence::Decay {
+                self.write_faded_row(y)
+            } else {
+                let scanline = self.visible_row(y, screen);
+                if self.force_redraw || self.last_grid[y] != scanline {
+                    self.write_row(y, scanline);
+                    self.last_grid[y] = scanline;
+                    true
+                } else {
+                    false
                 }
+            };
+
+            if changed {
+                dirty = match dirty {
+                    Some((first, _)) => Some((first, y)),
+                    None => Some((y, y)),
+                };
             }
         }
+
+        self.previous_grid = *screen;
+        self.force_redraw = false;
+
+        let (first, last) = match dirty {
+            Some(rows) => rows,
+            None => return,
+        };
+
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.pixels[..]),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        )
+        .expect("Failed to create image data, this shouldn't happen");
+
+        self.ctx
+            .put_image_data_with_dirty_x_and_dirty_y_and_dirty_width_and_dirty_height(
+                &image,
+                0.0,
+                0.0,
+                0.0,
+                first as f64,
+                SCREEN_WIDTH as f64,
+                (last - first + 1) as f64,
+            )
+            .expect("Failed to draw to canvas, this shouldn't happen");
     }
 }
@@ -1,5 +1,7 @@
 pub use self::canvas::*;
+pub use self::palette::{Palette, Rgba, PRESETS as PALETTE_PRESETS};
 pub use self::screen::*;
 
 mod canvas;
+mod palette;
 mod screen;
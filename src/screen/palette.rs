@@ -0,0 +1,230 @@
+use std::fmt;
+use std::str::FromStr;
+
+pub type Rgba = [u8; 4];
+
+// Colours used to draw the screen. Indexed by which bitplanes a pixel is
+// set in: 0 = background, 1 = first plane (the only one Chip-8 and SCHIP
+// have), 2 = second XO-CHIP plane, 3 = both planes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgba; 4],
+}
+
+// Black pixels over whatever is behind the canvas, the original look
+pub const DEFAULT: Palette = Palette {
+    colors: [
+        [0x00, 0x00, 0x00, 0x00],
+        [0x00, 0x00, 0x00, 0xFF],
+        [0x00, 0x00, 0x00, 0x80],
+        [0x00, 0x00, 0x00, 0xC0],
+    ],
+};
+
+pub const PRESETS: [(&str, Palette); 6] = [
+    ("default", DEFAULT),
+    (
+        "green-phosphor",
+        Palette {
+            colors: [
+                [0x0A, 0x1A, 0x0A, 0xFF],
+                [0x33, 0xFF, 0x66, 0xFF],
+                [0x1A, 0x80, 0x33, 0xFF],
+                [0x99, 0xFF, 0xB3, 0xFF],
+            ],
+        },
+    ),
+    (
+        "amber",
+        Palette {
+            colors: [
+                [0x1A, 0x0F, 0x00, 0xFF],
+                [0xFF, 0xB0, 0x00, 0xFF],
+                [0x80, 0x58, 0x00, 0xFF],
+                [0xFF, 0xD6, 0x80, 0xFF],
+            ],
+        },
+    ),
+    (
+        "lcd",
+        Palette {
+            colors: [
+                [0x9B, 0xBC, 0x0F, 0xFF],
+                [0x0F, 0x38, 0x0F, 0xFF],
+                [0x30, 0x62, 0x30, 0xFF],
+                [0x8B, 0xAC, 0x0F, 0xFF],
+            ],
+        },
+    ),
+    (
+        "high-contrast",
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0x00, 0xFF],
+                [0x00, 0xFF, 0xFF, 0xFF],
+            ],
+        },
+    ),
+    // Okabe-Ito colours, distinguishable with the common forms of colour blindness
+    (
+        "colour-blind",
+        Palette {
+            colors: [
+                [0x00, 0x00, 0x00, 0xFF],
+                [0xE6, 0x9F, 0x00, 0xFF],
+                [0x56, 0xB4, 0xE9, 0xFF],
+                [0xF0, 0xE4, 0x42, 0xFF],
+            ],
+        },
+    ),
+];
+
+impl Palette {
+    pub fn preset(name: &str) -> Option<Palette> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, palette)| *palette)
+    }
+
+    pub fn background(&self) -> Rgba {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Rgba {
+        self.colors[1]
+    }
+
+    // Mix between background (0) and foreground (255), used when fading pixels
+    pub fn fade(&self, intensity: u8) -> Rgba {
+        let (bg, fg) = (self.background(), self.foreground());
+
+        let mut color = [0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            let mixed =
+                (bg[i] as u32 * (255 - intensity as u32) + fg[i] as u32 * intensity as u32) / 255;
+            *channel = mixed as u8;
+        }
+
+        color
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+fn parse_color(hex: &str) -> Result<Rgba, ()> {
+    // "#RRGGBB" or "#RRGGBBAA"
+    let hex = hex.trim().strip_prefix('#').ok_or(())?;
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return Err(());
+    }
+
+    let mut color = [0, 0, 0, 0xFF];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| ())?;
+    }
+
+    Ok(color)
+}
+
+impl FromStr for Palette {
+    type Err = ();
+
+    // Either the name of a preset, or a comma separated list of colours.
+    // Two colours are background and foreground, four also set the XO-CHIP planes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Palette::preset(s) {
+            return Ok(palette);
+        }
+
+        let colors = s
+            .split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<Rgba>, ()>>()?;
+
+        match colors.len() {
+            2 => {
+                // Planes aren't used outside of XO-CHIP, both show as the foreground
+                Ok(Palette {
+                    colors: [colors[0], colors[1], colors[1], colors[1]],
+                })
+            }
+            4 => Ok(Palette {
+                colors: [colors[0], colors[1], colors[2], colors[3]],
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((name, _)) = PRESETS.iter().find(|(_, palette)| palette == self) {
+            return write!(f, "{}", name);
+        }
+
+        for (i, [r, g, b, a]) in self.colors.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#FF8000"), Ok([0xFF, 0x80, 0x00, 0xFF]));
+        assert_eq!(parse_color("#ff800040"), Ok([0xFF, 0x80, 0x00, 0x40]));
+        assert_eq!(parse_color("FF8000"), Err(()));
+        assert_eq!(parse_color("#FF80"), Err(()));
+        assert_eq!(parse_color("#GG8000"), Err(()));
+    }
+
+    #[test]
+    fn test_parse_palette() {
+        assert_eq!("amber".parse(), Ok(Palette::preset("amber").unwrap()));
+        assert_eq!(
+            "#000000,#ffffff".parse::<Palette>().unwrap().colors,
+            [
+                [0x00, 0x00, 0x00, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF],
+            ]
+        );
+        assert_eq!("#000000".parse::<Palette>(), Err(()));
+        assert_eq!("not-a-palette".parse::<Palette>(), Err(()));
+    }
+
+    #[test]
+    fn test_palette_round_trip() {
+        for (name, palette) in PRESETS.iter() {
+            assert_eq!(palette.to_string(), *name);
+        }
+
+        let custom: Palette = "#102030,#405060,#708090,#a0b0c0".parse().unwrap();
+        assert_eq!(custom.to_string().parse(), Ok(custom));
+    }
+
+    #[test]
+    fn test_fade() {
+        let palette = Palette::preset("high-contrast").unwrap();
+
+        assert_eq!(palette.fade(0), palette.background());
+        assert_eq!(palette.fade(255), palette.foreground());
+        assert_eq!(palette.fade(128), [128, 128, 128, 255]);
+    }
+}
@@ -1,8 +1,8 @@
-use super::canvas::Canvas;
+use super::canvas::{Canvas, RawGrid, SCREEN_HEIGHT, SCREEN_WIDTH};
 use crate::chip8::traits::Drawable;
 
 pub struct Screen {
-    raw: [u64; 32],
+    raw: RawGrid,
     canvas: Canvas,
 }
 
@@ -16,46 +16,100 @@ impl Screen {
 }
 
 impl Drawable for Screen {
-    fn flush(&self) {
+    fn flush(&mut self) {
         self.canvas.draw_grid(&self.raw)
     }
 
+    fn grid(&self) -> &RawGrid {
+        &self.raw
+    }
+
     fn clear(&mut self) {
         self.raw = [0; 32];
     }
 
-    fn write_sprite(&mut self, mut x: usize, mut y: usize, sprite: &[u8]) -> bool {
-        // Not sure if this is the standard way to handle drawing off screen.
-        // The only reference I've seen to it is in Mikolay's Chip-8 reference.
-        if x > 0x3F {
-            x %= 64
-        }
-        if y > 0x1F {
-            y %= 32
+    fn set_grid(&mut self, grid: &RawGrid) {
+        self.raw = *grid;
+    }
+
+    fn write_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        draw_sprite(&mut self.raw, x, y, sprite)
+    }
+}
+
+// XOR a sprite onto the grid, shared by every `Drawable` that keeps a `RawGrid`
+pub fn draw_sprite(raw: &mut RawGrid, mut x: usize, mut y: usize, sprite: &[u8]) -> bool {
+    // Not sure if this is the standard way to handle drawing off screen.
+    // The only reference I've seen to it is in Mikolay's Chip-8 reference.
+    if x > 0x3F {
+        x %= 64
+    }
+    if y > 0x1F {
+        y %= 32
+    }
+
+    // return true if sprite erases any pixels
+    let mut did_collide = false;
+
+    // I believe this function could be vectorized once the WASM SIMD
+    // spec makes it down the pipeline, but for now it doesn't matter
+    for (i, line) in sprite.iter().enumerate() {
+        // Chip-8 limitation, sprites are only 15 bytes long. Rows past the
+        // bottom of the screen are clipped.
+        if i > 15 || i + y >= SCREEN_HEIGHT {
+            break;
         }
 
-        // return true if sprite erases any pixels
-        let mut did_collide = false;
+        // Prep line to be XORd to the screen.
+        // Reverse bits so sprite isn't backwards (not sure why this happens)
+        // Shift into x position, truncating bits that go off the edge
+        let reversed = ((*line).reverse_bits() as u64).wrapping_shl(x as u32);
 
-        // I believe this function could be vectorized once the WASM SIMD
-        // spec makes it down the pipeline, but for now it doesn't matter
-        for (i, line) in sprite.iter().enumerate() {
-            // Chip-8 limitation, sprites are only 15 bytes long
-            if i > 15 {
-                break;
-            }
+        // Check if XOR will erase any pixels
+        let xord = raw[i + y] ^ reversed;
+        did_collide |= (raw[i + y] & reversed) != 0;
 
-            // Prep line to be XORd to the screen.
-            // Reverse bits so sprite isn't backwards (not sure why this happens)
-            // Shift into x position, truncating bits that go off the edge
-            let reversed = ((*line).reverse_bits() as u64).wrapping_shl(x as u32);
+        raw[i + y] = xord
+    }
+    return did_collide;
+}
 
-            // Check if XOR will erase any pixels
-            let xord = self.raw[i + y] ^ reversed;
-            did_collide |= (self.raw[i + y] & reversed) != 0;
+// Plain text picture of the grid, '#' for lit pixels and '.' for unlit ones,
+// one line per row
+pub fn grid_to_text(raw: &RawGrid) -> String {
+    let mut text = String::with_capacity((SCREEN_WIDTH + 1) * SCREEN_HEIGHT);
 
-            self.raw[i + y] = xord
+    for scanline in raw.iter() {
+        for x in 0..SCREEN_WIDTH {
+            text.push(if (scanline >> x) & 0x01 == 1 {
+                '#'
+            } else {
+                '.'
+            });
         }
-        return did_collide;
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_draw_sprite_clips() {
+        let mut raw: RawGrid = [0; SCREEN_HEIGHT];
+        let sprite = [0xFF; 4];
+
+        // Bottom right corner, only the top left of the sprite is visible
+        assert!(!draw_sprite(&mut raw, 60, 30, &sprite));
+        assert_eq!(raw[30], 0xF << 60);
+        assert_eq!(raw[31], 0xF << 60);
+
+        // Coordinates past the edge wrap around before drawing
+        assert!(draw_sprite(&mut raw, 60 + 64, 31 + 32, &sprite));
+        assert_eq!(raw[30], 0xF << 60);
+        assert_eq!(raw[31], 0);
     }
 }
@@ -1,65 +1,367 @@
-use crate::screen::{Canvas, Screen};
+use crate::screen::{Canvas, Palette, Persistence, Screen};
 
-use crate::keyboard::Keyboard;
+use crate::chip8::traits::Drawable;
+use crate::chip8::{guess_quirks, KeyQueue, Quirks, STEPS_PER_FRAME};
 
-use crate::chip8::Chip8;
+use crate::compare::Comparison;
 
-use crate::countdown::Countdown;
+use crate::debugger::Debugger;
 
+use crate::emulator::Emulator;
+
+use crate::gamepad::GamepadBindings;
+
+use crate::hotkeys::{Hotkey, Hotkeys};
+
+use crate::keyboard::{KeyBindings, Keyboard, KeyboardScope};
+
+use crate::gif::Recorder;
+
+use crate::png::encode_grid;
+
+use rand::random;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::console;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 fn window() -> web_sys::Window {
     web_sys::window().expect("no global `window` exists")
 }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
+// Chip-8 timers and the display run at 60Hz
+const FRAME_PERIOD: f64 = 1000.0 / 60.0;
+
+// If the page falls far behind (e.g. a background tab) don't try to catch up
+const MAX_FRAMES_PER_CALLBACK: u32 = 4;
+
+// Emulation speeds the speed hotkeys step through, as multiples of 60Hz
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+// Pixels per Chip-8 pixel in screenshots
+const SCREENSHOT_SCALE: usize = 10;
+
+#[wasm_bindgen(raw_module = "../js/download.ts")]
+extern "C" {
+    #[wasm_bindgen(js_name = downloadBytes)]
+    pub(crate) fn download_bytes(bytes: &[u8], filename: &str, mime_type: &str);
+}
+
+// Everything about the emulator that can be set from the page
+pub struct Options {
+    // `None` to guess them from the ROM
+    pub quirks: Option<Quirks>,
+    pub persistence: Persistence,
+
+    // Shared with the canvas so it can be changed while running
+    pub palette: Rc<Cell<Palette>>,
+
+    // Every presented frame is captured while a recording is in progress
+    pub recorder: Rc<RefCell<Option<Recorder>>>,
+
+    // Input bindings for the running ROM, can also change while running
+    pub keys: Rc<RefCell<KeyBindings>>,
+    pub gamepad: Rc<Cell<GamepadBindings>>,
+    pub hotkeys: Rc<RefCell<Hotkeys>>,
+}
+
+// Where an emulator lives on the page. Every emulator has its own canvas,
+// keyboard focus and animation loop, so several can run side by side.
+pub struct View {
+    pub canvas: web_sys::HtmlCanvasElement,
+    pub keyboard: KeyboardScope,
+    // The page's debugger and error card, only one emulator can have them
+    pub debugger: bool,
+}
+
+// An emulator running on the page, until `stop` is called
+pub struct Instance {
+    emulator: Rc<RefCell<Emulator>>,
+    paused: Rc<Cell<bool>>,
+    stopped: Rc<Cell<bool>>,
+}
+
+impl Instance {
+    pub fn emulator(&self) -> &Rc<RefCell<Emulator>> {
+        &self.emulator
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.set(paused);
+    }
+
+    // Ends the animation loop, which lets go of the emulator and its inputs
+    pub fn stop(&self) {
+        self.stopped.set(true);
+    }
+}
+
+fn request_animation_frame(f: &Closure<dyn FnMut(f64)>) {
     window()
         .request_animation_frame(f.as_ref().unchecked_ref())
         .expect("should register `requestAnimationFrame` OK");
 }
 
-pub fn run_emulator(rom: &[u8]) -> Result<(), String> {
-    // Initialize emulator
-    let timer = Box::new(Countdown::new());
+// Call `callback` with the time on every animation frame until `stopped` is set
+// https://rustwasm.github.io/wasm-bindgen/examples/request-animation-frame.html
+fn animate(stopped: Rc<Cell<bool>>, mut callback: impl FnMut(f64) + 'static) {
+    let f = Rc::new(RefCell::new(None));
+    let g = f.clone();
+    *g.borrow_mut() = Some(Closure::wrap(Box::new(move |now: f64| {
+        // Dropping the closure lets go of everything it holds
+        if stopped.get() {
+            let _ = f.borrow_mut().take();
+            return;
+        }
+
+        callback(now);
+
+        // Schedule ourself for another requestAnimationFrame callback.
+        request_animation_frame(f.borrow().as_ref().unwrap());
+    }) as Box<dyn FnMut(f64)>));
+    request_animation_frame(g.borrow().as_ref().unwrap());
+}
 
-    let keyboard = Box::new(Keyboard::new());
+pub fn run_emulator(rom: &[u8], options: Options, view: View) -> Result<Instance, String> {
+    // Initialize emulator
+    let palette = options.palette.clone();
+    let mut canvas = Canvas::new(&view.canvas, options.palette);
+    canvas.set_persistence(options.persistence);
+    let screen = Box::new(Screen::new_empty(canvas));
 
-    let screen = Box::new(Screen::new_empty(Canvas::new("canvas")));
+    let keyboard = Keyboard::new(
+        view.keyboard,
+        options.keys,
+        options.gamepad,
+        options.hotkeys,
+    );
 
-    let mut chip8 = Chip8::new(screen, keyboard, timer);
-    match chip8.init_memory(rom) {
-        Ok(_) => {}
+    let emulator = match Emulator::new(screen, keyboard, rom, options.quirks) {
+        Ok(emulator) => emulator,
         Err(e) => return Err(e.to_string()),
+    };
+
+    let instance = Instance {
+        emulator: Rc::new(RefCell::new(emulator)),
+        paused: Rc::new(Cell::new(false)),
+        stopped: Rc::new(Cell::new(false)),
+    };
+    let emulator = instance.emulator.clone();
+    let paused = instance.paused.clone();
+    let stopped = instance.stopped.clone();
+
+    // The debugger needs to reach the emulator from its button handlers
+    let debugger = match view.debugger {
+        true => {
+            let debugger = Rc::new(Debugger::new(paused.clone()));
+            Debugger::attach(&debugger, &emulator);
+            Some(debugger)
+        }
+        false => None,
+    };
+    let set_status = {
+        let debugger = debugger.clone();
+        move |status: &str| {
+            if let Some(debugger) = &debugger {
+                debugger.set_status(status);
+            }
+        }
+    };
+
+    if options.quirks.is_none() {
+        set_status(&format!("Guessed {}", guess_quirks(rom)));
     }
 
+    let recorder = options.recorder;
+
     // Step execution on animation frame
-    // https://rustwasm.github.io/wasm-bindgen/examples/request-animation-frame.html
-    let f = Rc::new(RefCell::new(None));
-    let g = f.clone();
-    *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        // assuming browser calls request animation frame ~60 times per second to
-        // get a target speed of 500Hz we have to step execution 500/60 = ~8 times
-        // TODO: measure time between animation frames and dynamically adjust
-        // steps per frame to reach a target frame rate
-        for _ in 0..9 {
-            match chip8.step_execution() {
-                Ok(_) => {}
-                Err(e) => {
-                    console::warn_1(&JsValue::from(e.to_string()));
-                    return;
+    let mut last_time: Option<f64> = None;
+    let mut lag = 0.0;
+    let mut speed = NORMAL_SPEED;
+    animate(stopped, move |now: f64| {
+        // Animation frames don't always arrive at 60Hz, so the time between them
+        // is accumulated and the emulator runs one frame per 60Hz tick that passed
+        lag += now - last_time.unwrap_or(now);
+        last_time = Some(now);
+
+        let mut emulator = emulator.borrow_mut();
+
+        // Hotkeys are handled even while paused
+        let mut advance = false;
+        for hotkey in emulator.take_hotkeys() {
+            match hotkey {
+                Hotkey::Pause => paused.set(!paused.get()),
+                Hotkey::FrameAdvance => {
+                    paused.set(true);
+                    advance = true;
+                }
+                Hotkey::Reset => match emulator.restart() {
+                    Ok(()) => set_status("Reset"),
+                    Err(e) => set_status(&e.to_string()),
+                },
+                Hotkey::SpeedUp | Hotkey::SlowDown => {
+                    speed = match hotkey {
+                        Hotkey::SpeedUp => (speed + 1).min(SPEEDS.len() - 1),
+                        _ => speed.saturating_sub(1),
+                    };
+                    set_status(&format!("Speed {}x", SPEEDS[speed]));
+                }
+                Hotkey::QuickSave => {
+                    emulator.quick_save();
+                    set_status("Saved state");
                 }
-            };
+                Hotkey::QuickLoad => match emulator.quick_load() {
+                    Ok(true) => set_status("Loaded state"),
+                    Ok(false) => set_status("Nothing saved yet"),
+                    Err(e) => set_status(&e.to_string()),
+                },
+                Hotkey::Screenshot => {
+                    match encode_grid(emulator.chip8().screen(), SCREENSHOT_SCALE, &palette.get()) {
+                        Ok(png) => download_bytes(&png, "chip8.png", "image/png"),
+                        Err(e) => set_status(&e),
+                    }
+                }
+            }
         }
 
-        // Schedule ourself for another requestAnimationFrame callback.
-        request_animation_frame(f.borrow().as_ref().unwrap());
-    }) as Box<dyn FnMut()>));
-    request_animation_frame(g.borrow().as_ref().unwrap());
+        if paused.get() {
+            lag = 0.0;
+        }
+
+        // Faster speeds run more frames in the same time, each frame still
+        // sees the keys pressed during it
+        let frame_period = FRAME_PERIOD / SPEEDS[speed];
+        let max_frames = MAX_FRAMES_PER_CALLBACK * SPEEDS[speed].ceil() as u32;
+
+        let mut frames = 0;
+        while (lag >= frame_period && !paused.get()) || advance {
+            // The oldest frame that hasn't run yet started `lag` ms ago
+            let since = now - lag;
+            if let Err(e) = emulator.run_frame(since, since + frame_period) {
+                // Pause instead of stopping so the state that caused
+                // the error can be inspected
+                match &debugger {
+                    Some(debugger) => {
+                        debugger.show_error(emulator.crash_report(&e, js_sys::Date::now() as u64))
+                    }
+                    None => console::warn_1(&JsValue::from(e.to_string())),
+                }
+                paused.set(true);
+            }
+
+            if let Some(recorder) = recorder.borrow_mut().as_mut() {
+                recorder.push(emulator.chip8().screen());
+            }
+
+            if advance {
+                advance = false;
+                continue;
+            }
+
+            lag -= frame_period;
+            frames += 1;
+            if frames == max_frames {
+                lag = 0.0;
+            }
+        }
+        if let Some(debugger) = &debugger {
+            debugger.render(emulator.chip8());
+        }
+    });
+
+    Ok(instance)
+}
+
+// Where a quirk comparison lives on the page
+pub struct ComparisonView {
+    // Left and right machines
+    pub canvases: [web_sys::HtmlCanvasElement; 2],
+    pub keyboard: KeyboardScope,
+    // Shows the instruction the machines first differed at
+    pub divergence: web_sys::Element,
+}
+
+// Run the ROM on two machines with different quirks and the same keys, see
+// `Comparison`. It runs until `stopped` is set, the pause hotkey pauses it.
+// `options.quirks` isn't used, each machine has its own.
+pub fn run_comparison(
+    rom: &[u8],
+    quirks: [Quirks; 2],
+    options: Options,
+    view: ComparisonView,
+    stopped: Rc<Cell<bool>>,
+) -> Result<(), String> {
+    let [left, right] = &view.canvases;
+    let screens: [Box<dyn Drawable>; 2] = [left, right].map(|canvas| {
+        let mut canvas = Canvas::new(canvas, options.palette.clone());
+        canvas.set_persistence(options.persistence);
+        Box::new(Screen::new_empty(canvas)) as Box<dyn Drawable>
+    });
+
+    let mut comparison =
+        Comparison::new(screens, rom, quirks, random()).map_err(|e| e.to_string())?;
+    let mut keyboard = Keyboard::new(
+        view.keyboard,
+        options.keys,
+        options.gamepad,
+        options.hotkeys,
+    );
+    let mut keys = KeyQueue::new();
+    let divergence = view.divergence;
+    divergence.set_text_content(Some("No difference yet"));
+
+    let mut last_time: Option<f64> = None;
+    let mut lag = 0.0;
+    let mut paused = false;
+    let mut shown_divergence = false;
+    animate(stopped, move |now: f64| {
+        lag += now - last_time.unwrap_or(now);
+        last_time = Some(now);
+
+        for hotkey in keyboard.take_hotkeys() {
+            if hotkey == Hotkey::Pause {
+                paused = !paused;
+            }
+        }
+        if paused {
+            lag = 0.0;
+        }
+
+        let mut frames = 0;
+        while lag >= FRAME_PERIOD && !paused {
+            let since = now - lag;
+            keyboard.take_events(&mut keys, since + FRAME_PERIOD);
+            let frame_keys = keys.frame(since, since + FRAME_PERIOD, STEPS_PER_FRAME);
+
+            if let Err(e) = comparison.run_frame(&frame_keys) {
+                divergence.set_text_content(Some(&format!("Stopped with {}", e)));
+                paused = true;
+            }
+
+            let polled_keys = comparison.left().polled_keys() | comparison.right().polled_keys();
+            keyboard.set_polled_keys(polled_keys);
+
+            lag -= FRAME_PERIOD;
+            frames += 1;
+            if frames == MAX_FRAMES_PER_CALLBACK {
+                lag = 0.0;
+            }
+        }
+
+        if !shown_divergence {
+            if let Some(first) = comparison.divergence() {
+                divergence.set_text_content(Some(&first.to_string()));
+                shown_divergence = true;
+            }
+        }
+    });
 
     Ok(())
 }
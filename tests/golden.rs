@@ -0,0 +1,160 @@
+// Golden screen tests. Each case runs a bundled ROM headlessly for a fixed
+// number of frames, with fixed key presses and random seed, then compares the
+// screen with a picture checked in under tests/golden/.
+//
+// After an intended change to what a ROM draws, regenerate the pictures with
+//
+//   UPDATE_GOLDEN=1 cargo test --test golden
+//
+// and check the differences in git before committing them.
+use chip_8::chip8::{Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+use chip_8::screen::grid_to_text;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const SEED: u64 = 0;
+
+struct Case {
+    // Name of the picture in tests/golden/
+    name: &'static str,
+
+    // Relative to static/roms/
+    rom: &'static str,
+    frames: u64,
+
+    // Key to hold from a frame on, in frame order
+    keys: &'static [(u64, Option<u8>)],
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "test_opcode",
+        rom: "test_opcode/test_opcode.ch8",
+        frames: 100,
+        keys: &[],
+    },
+    Case {
+        name: "ibm_logo",
+        rom: "chip8_program_pack/programs/IBM Logo.ch8",
+        frames: 60,
+        keys: &[],
+    },
+    Case {
+        name: "maze",
+        rom: "chip8_program_pack/demos/Maze [David Winter, 199x].ch8",
+        frames: 120,
+        keys: &[],
+    },
+    Case {
+        name: "trip8",
+        rom: "chip8_program_pack/demos/Trip8 Demo (2008) [Revival Studios].ch8",
+        frames: 300,
+        keys: &[],
+    },
+    Case {
+        name: "keypad_test",
+        rom: "chip8_program_pack/programs/Keypad Test [Hap, 2006].ch8",
+        frames: 60,
+        keys: &[(20, Some(0xA)), (40, None)],
+    },
+    Case {
+        name: "pong",
+        rom: "chip8_program_pack/games/Pong (1 player).ch8",
+        frames: 240,
+        keys: &[(30, Some(0x4)), (40, None), (100, Some(0x1)), (105, None)],
+    },
+    Case {
+        name: "space_invaders",
+        rom: "chip8_program_pack/games/Space Invaders [David Winter].ch8",
+        frames: 400,
+        keys: &[(100, Some(0x5)), (110, None), (200, Some(0x6)), (260, None)],
+    },
+];
+
+fn path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn run(case: &Case) -> String {
+    let rom = fs::read(path("static/roms").join(case.rom)).expect("could not read ROM");
+
+    let mut headless = Headless::new(&rom, Quirks::default()).expect("could not load ROM");
+    headless.chip8_mut().set_seed(SEED);
+
+    let mut keys = case.keys.iter().peekable();
+    while headless.frame() < case.frames {
+        while let Some((_, key)) = keys.next_if(|(frame, _)| *frame <= headless.frame()) {
+            headless.set_key(*key);
+        }
+
+        if let Err(e) = headless.run_frame(STEPS_PER_FRAME) {
+            panic!("{} failed at frame {}: {}", case.name, headless.frame(), e);
+        }
+    }
+
+    grid_to_text(headless.chip8().screen())
+}
+
+// Both pictures side by side, rows that differ are marked with '!'
+fn diff(expected: &str, actual: &str) -> String {
+    let mut report = String::new();
+
+    let mut expected_rows = expected.lines();
+    let mut actual_rows = actual.lines();
+    let mut row = 0;
+    loop {
+        let (expected_row, actual_row) = match (expected_rows.next(), actual_rows.next()) {
+            (None, None) => break,
+            (e, a) => (e.unwrap_or(""), a.unwrap_or("")),
+        };
+
+        let marker = if expected_row == actual_row { ' ' } else { '!' };
+        report += &format!("{:2} {:64} {} {}\n", row, expected_row, marker, actual_row);
+        row += 1;
+    }
+
+    report
+}
+
+#[test]
+fn golden_screens() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for case in CASES {
+        let actual = run(case);
+        let golden = path("tests/golden").join(format!("{}.txt", case.name));
+
+        if update {
+            fs::write(&golden, &actual).expect("could not write golden picture");
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+            panic!(
+                "missing {}, run with UPDATE_GOLDEN=1 to create it",
+                golden.display()
+            )
+        });
+
+        if expected != actual {
+            failures.push(format!(
+                "{} after {} frames\n   {:64}   {}\n{}",
+                case.name,
+                case.frames,
+                "expected",
+                "actual",
+                diff(&expected, &actual)
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "screens differ from tests/golden/:\n\n{}",
+        failures.join("\n")
+    );
+}
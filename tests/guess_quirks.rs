@@ -0,0 +1,74 @@
+// Quirks guessed for the bundled ROMs. Each ROM is run headlessly without
+// input, once with no quirks and once with the guessed ones, and a guess
+// must never stop a ROM that works without quirks from running or drawing.
+use chip_8::chip8::{guess_quirks, Quirks, STEPS_PER_FRAME};
+use chip_8::headless::Headless;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FRAMES: u64 = 600;
+const SEED: u64 = 0;
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext == "ch8") {
+            roms.push(path);
+        }
+    }
+}
+
+// Frames the screen changed on, or `None` if the ROM stopped with an error
+fn frames_drawn(rom: &[u8], quirks: Quirks) -> Option<u64> {
+    let mut headless = Headless::new(rom, quirks).ok()?;
+    headless.chip8_mut().set_seed(SEED);
+
+    let mut drawn = 0;
+    let mut last_screen = *headless.chip8().screen();
+    while headless.frame() < FRAMES {
+        headless.run_frame(STEPS_PER_FRAME).ok()?;
+
+        if *headless.chip8().screen() != last_screen {
+            last_screen = *headless.chip8().screen();
+            drawn += 1;
+        }
+    }
+
+    Some(drawn)
+}
+
+#[test]
+fn test_guesses_keep_bundled_roms_running() {
+    let mut roms = Vec::new();
+    find_roms(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("static/roms"),
+        &mut roms,
+    );
+    assert!(!roms.is_empty());
+
+    let mut broken = Vec::new();
+    for path in roms {
+        let rom = fs::read(&path).unwrap();
+        let guess = guess_quirks(&rom);
+
+        // Nothing to go on means nothing changes
+        if guess.evidence.is_empty() {
+            assert_eq!(guess.quirks, Quirks::default(), "{}", path.display());
+            continue;
+        }
+
+        let works = |quirks| frames_drawn(&rom, quirks).is_some_and(|drawn| drawn > 0);
+        if works(Quirks::default()) && !works(guess.quirks) {
+            broken.push(format!("{} with {}", path.display(), guess));
+        }
+    }
+
+    assert!(
+        broken.is_empty(),
+        "Guessed quirks broke:\n{}",
+        broken.join("\n")
+    );
+}